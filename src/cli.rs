@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+pub const USAGE: &str =
+    "usage: snes_input_display [settings.toml] [--record <file>] [--replay <file> [--player <n>]]
+       snes_input_display [settings.toml] --diagnose
+       snes_input_display --export-bk2 <input log> <movie.bk2> [--rom <file>]";
+
+/// Command line arguments.
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    /// Path of the settings file, defaults to the platform configuration directory.
+    pub config_path: Option<String>,
+    /// Start recording the session to this input log file right away.
+    pub record_path: Option<PathBuf>,
+    /// Replay this input log or emulator movie instead of reading inputs from usb2snes.
+    pub replay_path: Option<PathBuf>,
+    /// Player whose inputs are replayed from a movie, 0 based (`--player` is 1 based).
+    pub player: usize,
+    /// Convert this input log to a BizHawk movie and exit: `(input log, movie)`.
+    pub export_bk2: Option<(PathBuf, PathBuf)>,
+    /// ROM the exported session was played on, hashed in the movie header.
+    pub rom_path: Option<PathBuf>,
+    /// Measure the read latency and sample jitter without opening a window, then exit.
+    pub diagnose: bool,
+}
+
+impl CliArgs {
+    /// Parses the arguments, without the program name.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut cli_args = CliArgs::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record" => {
+                    let path = args.next().ok_or("--record expects a file path")?;
+                    cli_args.record_path = Some(PathBuf::from(path));
+                }
+                "--replay" => {
+                    let path = args.next().ok_or("--replay expects a file path")?;
+                    cli_args.replay_path = Some(PathBuf::from(path));
+                }
+                "--player" => {
+                    let player = args.next().ok_or("--player expects a player number")?;
+                    cli_args.player = match player.parse::<usize>() {
+                        Ok(p @ 1..=5) => p - 1,
+                        _ => return Err("--player expects a number between 1 and 5".into()),
+                    };
+                }
+                "--export-bk2" => {
+                    let (Some(input), Some(output)) = (args.next(), args.next()) else {
+                        return Err("--export-bk2 expects an input log and a .bk2 path".into());
+                    };
+                    cli_args.export_bk2 = Some((PathBuf::from(input), PathBuf::from(output)));
+                }
+                "--rom" => {
+                    let path = args.next().ok_or("--rom expects a file path")?;
+                    cli_args.rom_path = Some(PathBuf::from(path));
+                }
+                "--diagnose" => cli_args.diagnose = true,
+                flag if flag.starts_with("--") => {
+                    return Err(format!("unknown option {flag}\n{USAGE}").into())
+                }
+                _ if cli_args.config_path.is_none() => cli_args.config_path = Some(arg),
+                _ => return Err(format!("unexpected argument {arg}\n{USAGE}").into()),
+            }
+        }
+        Ok(cli_args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, Box<dyn Error>> {
+        CliArgs::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_config_path_and_record_flag() {
+        let args = parse(&["settings.toml", "--record", "run.sidlog"]).unwrap();
+        assert_eq!(args.config_path.as_deref(), Some("settings.toml"));
+        assert_eq!(args.record_path, Some(PathBuf::from("run.sidlog")));
+        assert_eq!(args.replay_path, None);
+
+        assert_eq!(parse(&[]).unwrap(), CliArgs::default());
+
+        let args = parse(&["--replay", "tas.bk2", "--player", "2"]).unwrap();
+        assert_eq!(args.replay_path, Some(PathBuf::from("tas.bk2")));
+        assert_eq!(args.player, 1);
+
+        assert!(parse(&["settings.toml", "--diagnose"]).unwrap().diagnose);
+
+        let args = parse(&["--export-bk2", "run.sidlog", "run.bk2", "--rom", "smw.sfc"]).unwrap();
+        assert_eq!(args.rom_path, Some(PathBuf::from("smw.sfc")));
+    }
+
+    #[test]
+    fn rejects_unknown_and_incomplete_flags() {
+        assert!(parse(&["--record"]).is_err());
+        assert!(parse(&["--export-bk2", "run.sidlog"]).is_err());
+        assert!(parse(&["--rom"]).is_err());
+        assert!(parse(&["--player", "6"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["a.toml", "b.toml"]).is_err());
+    }
+}
@@ -5,13 +5,22 @@ use std::fs::{read_to_string, write, File};
 use std::path::{Path, PathBuf};
 
 use crate::controller::controller_impl::ControllerConfig;
-use crate::skins::skin::SkinConfig;
+use crate::input_source::{poller::PollingConfig, usb2snes::DeviceConfig};
+use crate::recording::RecordingConfig;
+use crate::skins::skin::{Arrangement, SkinConfig};
+use crate::status::StatusConfig;
+use crate::timeline::TimelineConfig;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct AppConfig {
     pub controller: ControllerConfig,
     pub skin: SkinConfig,
     pub usb2snes: Option<USB2SnesEndpoint>,
+    pub recording: Option<RecordingConfig>,
+    pub timeline: Option<TimelineConfig>,
+    pub polling: Option<PollingConfig>,
+    pub device: Option<DeviceConfig>,
+    pub status: Option<StatusConfig>,
 }
 
 impl AppConfig {
@@ -66,8 +75,16 @@ impl AppConfig {
                 skins_path: default_skins_dir_path,
                 skin_name: "skin_folder_name".to_string(),
                 skin_theme: "skin_theme".to_string(),
+                arrangement: Arrangement::default(),
+                mouse: None,
+                light_gun: None,
             },
             usb2snes: Some(USB2SnesEndpoint::default()),
+            recording: None,
+            timeline: None,
+            polling: None,
+            device: None,
+            status: None,
         };
         let toml = toml::to_string(&config)?;
         File::create(path)?;
@@ -1,18 +1,75 @@
+pub mod address_expr;
+pub mod bit_mapping;
 pub mod button_state;
 pub mod buttons_iter;
 pub mod controller_addresses;
 pub mod controller_impl;
+pub mod frame_counter;
+pub mod light_gun;
+pub mod mouse;
+pub mod port;
 pub mod pressed;
+pub mod rom_header;
+pub mod selector;
 
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
     use crate::controller::{
+        address_expr::AddressExpr,
+        bit_mapping::{BitMapping, InputDecoding},
         button_state::ButtonState,
-        controller_impl::{ControllerConfig, ControllerData},
+        controller_addresses::ControllerAddresses,
+        controller_impl::{ControllerConfig, ControllerData, ControllerLayouts},
+        frame_counter::FrameCount,
+        light_gun::LightGunState,
+        mouse::MouseState,
+        port::{ControllerPort, PortState},
         pressed::Pressed,
+        rom_header::RomHeader,
     };
+    use crate::input_source::fake_memory::FakeMemory;
+
+    fn joypad(port: &ControllerPort) -> ControllerAddresses {
+        match port {
+            ControllerPort::Joypad(addresses) => addresses.clone(),
+            other => panic!("expected a joypad port, got {other:?}"),
+        }
+    }
+
+    #[test]
+    pub fn test_button_state_operations() {
+        let b_y_right: ButtonState = [Pressed::B, Pressed::Y, Pressed::Right]
+            .into_iter()
+            .collect();
+        let b_a: ButtonState = [Pressed::A, Pressed::B].into_iter().collect();
+        assert_eq!(b_y_right.to_le_bytes(), [0x00, 0xC1]);
+        assert!(b_y_right.contains(Pressed::Right));
+        assert!(!b_y_right.contains(Pressed::A));
+        assert_eq!(b_y_right | b_a, "A+B+Y+Right".parse().unwrap());
+        assert_eq!(b_y_right & b_a, ButtonState::from(Pressed::B));
+        assert_eq!(b_y_right - b_a, "Y+Right".parse().unwrap());
+        assert_eq!(b_a.just_pressed(b_y_right), ButtonState::from(Pressed::A));
+        assert_eq!(b_a.just_released(b_y_right), "Y+Right".parse().unwrap());
+        assert!(b_a.just_pressed(b_a).is_empty());
+
+        // Display and FromStr, in register order
+        assert_eq!(b_y_right.to_string(), "B+Y+Right");
+        assert_eq!(ButtonState::default().to_string(), "None");
+        assert_eq!(" right + b+Y ".parse(), Ok(b_y_right));
+        assert_eq!("none".parse(), Ok(ButtonState::default()));
+        assert!("B+Turbo".parse::<ButtonState>().is_err());
+        assert!("B++A".parse::<ButtonState>().is_err());
+
+        // Serialized as the same notation
+        assert_eq!(serde_json::to_string(&b_a).unwrap(), r#""B+A""#);
+        assert_eq!(
+            serde_json::from_str::<Vec<ButtonState>>(r#"["Select+Start", "None"]"#).unwrap(),
+            ["Select+Start".parse().unwrap(), ButtonState::default()]
+        );
+        assert!(serde_json::from_str::<ButtonState>(r#""Home""#).is_err());
+    }
 
     #[test]
     pub fn test_buttons_iter() {
@@ -27,6 +84,510 @@ mod tests {
         assert_eq!(None, no_buttons_iter.next());
     }
 
+    #[test]
+    pub fn test_pushed_reads_low_and_high_bytes() {
+        let mut memory = FakeMemory::new([(0xF500F0, 0x03), (0xF500F2, 0xA0)]);
+        let addresses = ControllerAddresses {
+            address_low: AddressExpr::from(0xF500F2),
+            address_high: AddressExpr::from(0xF500F0),
+            decoding: InputDecoding::default(),
+        };
+
+        let mut buttons = addresses.pushed(&mut memory).unwrap().iter();
+        assert_eq!(Some(Pressed::Left), buttons.next());
+        assert_eq!(Some(Pressed::Right), buttons.next());
+        assert_eq!(Some(Pressed::A), buttons.next());
+        assert_eq!(Some(Pressed::L), buttons.next());
+        assert_eq!(None, buttons.next());
+    }
+
+    #[test]
+    pub fn test_far_apart_and_reversed_addresses() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Far": { "address_low": "F50010", "address_high": "F70010" },
+                    "Reversed": { "address_low": "F50DA4", "address_high": "F50DA2" },
+                    "Banks": { "address_low": "000000", "address_high": "FFFFFF" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut memory = FakeMemory::new([
+            (0xF50010, 0x80),
+            (0xF70010, 0x80),
+            (0xF50DA4, 0x40),
+            (0xF50DA2, 0x40),
+            (0x000000, 0x80),
+            (0xFFFFFF, 0x01),
+        ])
+        .strict();
+        let pressed = |layout: &str, memory: &mut FakeMemory| -> Vec<Pressed> {
+            let state = layouts.layouts[layout].read(memory).unwrap();
+            state.ports[0].joypad().unwrap().iter().collect()
+        };
+
+        assert_eq!(pressed("Far", &mut memory), vec![Pressed::B, Pressed::A]);
+        assert_eq!(
+            pressed("Reversed", &mut memory),
+            vec![Pressed::Y, Pressed::X]
+        );
+        assert_eq!(
+            pressed("Banks", &mut memory),
+            vec![Pressed::Right, Pressed::A]
+        );
+        // One request per layout, however far apart the bytes are
+        assert_eq!(memory.requests.len(), 3);
+
+        for invalid in [
+            r#"{ "address_low": "F50DA4", "address_high": "F50DA4" }"#,
+            r#"{ "address_low": "F50DA4", "address_high": "1000000" }"#,
+            r#"{ "device": "mouse", "report": "1000000" }"#,
+            r#"{ "device": "lightgun", "buttons": "F50010", "x": "F50011", "y": "FF000000" }"#,
+        ] {
+            let layouts = format!(r#"{{ "layouts": {{ "Invalid": {invalid} }} }}"#);
+            assert!(
+                serde_json::from_str::<ControllerLayouts>(&layouts).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_pointer_addresses() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Pointer": { "address_low": "[F50010]+1", "address_high": "[F50010]" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        // The inputs struct is at $7E2000, far from the pointer
+        let mut memory = FakeMemory::new([
+            (0xF50010, 0x00),
+            (0xF50011, 0x20),
+            (0xF52000, 0x80),
+            (0xF52001, 0x80),
+        ]);
+        let states = layouts.layouts["Pointer"].pushed(&mut memory).unwrap();
+        let pressed: Vec<Pressed> = states[0].joypad().unwrap().iter().collect();
+        assert_eq!(pressed, vec![Pressed::B, Pressed::A]);
+
+        let invalid =
+            r#"{ "layouts": { "Bad": { "address_low": "[F50010", "address_high": "F50011" } } }"#;
+        let error = serde_json::from_str::<ControllerLayouts>(invalid).unwrap_err();
+        assert!(error.to_string().contains("expected ']'"), "{error}");
+    }
+
+    #[test]
+    pub fn test_frame_counter() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "SMW": {
+                        "address_low": "F50DA4",
+                        "address_high": "F50DA2",
+                        "frame_counter": { "address": "F50013" }
+                    },
+                    "Wide": {
+                        "address_low": "F50DA4",
+                        "address_high": "F50DA2",
+                        "frame_counter": { "address": "F50100", "bytes": 2 }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut memory = FakeMemory::new([
+            (0xF50013, 0x2A),
+            (0xF50014, 0x01),
+            (0xF50100, 0x34),
+            (0xF50101, 0x12),
+            (0xF50DA2, 0x80),
+        ]);
+
+        let frame = |value| FrameCount { value, bytes: 1 };
+        let state = layouts.layouts["SMW"].read(&mut memory).unwrap();
+        assert_eq!(state.frame, Some(frame(0x2A)));
+        assert_eq!(
+            state.ports[0].joypad().unwrap().iter().next(),
+            Some(Pressed::B)
+        );
+        let state = layouts.layouts["Wide"].read(&mut memory).unwrap();
+        assert_eq!(state.frame.unwrap().value, 0x1234);
+
+        assert_eq!(frame(0x01).frames_since(frame(0xFF)), Some(2));
+        assert_eq!(frame(0x01).frames_since(frame(0x02)), None);
+
+        let invalid = r#"{ "layouts": { "Bad": {
+            "address_low": "F50DA4", "address_high": "F50DA2",
+            "frame_counter": { "address": "F50013", "bytes": 5 }
+        } } }"#;
+        assert!(serde_json::from_str::<ControllerLayouts>(invalid).is_err());
+    }
+
+    #[test]
+    pub fn test_multi_port_layouts() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Single": { "address_low": "F50DA4", "address_high": "F50DA2" },
+                    "Co-op": {
+                        "ports": [
+                            { "address_low": "F50010", "address_high": "F50011" },
+                            { "address_low": "F50012", "address_high": "F50013" }
+                        ]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(layouts.layouts["Single"].ports.len(), 1);
+        let co_op = &layouts.layouts["Co-op"];
+        assert_eq!(co_op.ports.len(), 2);
+        assert_eq!(
+            joypad(&co_op.ports[1]).address_high,
+            AddressExpr::from(0xF50013)
+        );
+
+        let mut memory = FakeMemory::new([(0xF50011, 0x80), (0xF50012, 0x80)]);
+        let states: Vec<Vec<Pressed>> = co_op
+            .pushed(&mut memory)
+            .unwrap()
+            .iter()
+            .map(|s| s.joypad().unwrap().iter().collect())
+            .collect();
+        assert_eq!(states, vec![vec![Pressed::B], vec![Pressed::A]]);
+    }
+
+    #[test]
+    pub fn test_bit_mapping() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Inverted": { "address_low": "F50010", "address_high": "F50011", "invert": true, "mask": "FFF0" },
+                    "Single byte": {
+                        "address_low": "F50020",
+                        "address_high": "F50020",
+                        "mapping": {
+                            "b": { "byte": 0, "bit": 0 }, "a": { "byte": 0, "bit": 1 },
+                            "up": { "byte": 0, "bit": 4 }, "down": { "byte": 0, "bit": 5 },
+                            "left": { "byte": 0, "bit": 6 }, "right": { "byte": 0, "bit": 7 },
+                            "start": { "byte": 0, "bit": 3 },
+                            "y": null, "x": null, "l": null, "r": null, "select": null
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        // Active low with garbage in the unused low nibble: only B and A are held
+        let mut memory = FakeMemory::new([(0xF50010, 0x7F), (0xF50011, 0x7F), (0xF50020, 0x89)]);
+        let pressed = |layout: &str, memory: &mut FakeMemory| -> Vec<Pressed> {
+            let states = layouts.layouts[layout].pushed(memory).unwrap();
+            states[0].joypad().unwrap().iter().collect()
+        };
+        assert_eq!(
+            pressed("Inverted", &mut memory),
+            vec![Pressed::B, Pressed::A]
+        );
+        assert_eq!(
+            pressed("Single byte", &mut memory),
+            vec![Pressed::B, Pressed::Start, Pressed::Right]
+        );
+
+        let mapping = |entries: &str| {
+            format!(
+                r#"{{ "layouts": {{ "Bad": {{ "address_low": "F50020", "address_high": "F50021", "mapping": {{ {entries} }} }} }} }}"#
+            )
+        };
+        let all_but_b = r#""y": null, "x": null, "l": null, "r": null, "select": null, "start": null,
+            "up": null, "down": null, "left": null, "right": null, "a": { "byte": 0, "bit": 0 }"#;
+        for invalid in [
+            mapping(all_but_b),
+            mapping(&format!(r#"{all_but_b}, "b": {{ "byte": 0, "bit": 0 }}"#)),
+            mapping(&format!(r#"{all_but_b}, "b": {{ "byte": 2, "bit": 0 }}"#)),
+        ] {
+            assert!(
+                serde_json::from_str::<ControllerLayouts>(&invalid).is_err(),
+                "{invalid}"
+            );
+        }
+        // The first b would be silently dropped
+        let twice = format!(
+            r#"{all_but_b}, "b": {{ "byte": 1, "bit": 0 }}, "b": {{ "byte": 1, "bit": 1 }}"#
+        );
+        let error = ControllerLayouts::from_json(&mapping(&twice)).unwrap_err();
+        assert!(error.to_string().starts_with("duplicate key b"), "{error}");
+        let error = serde_json::from_str::<BitMapping>(&format!("{{ {twice} }}")).unwrap_err();
+        assert!(
+            error.to_string().starts_with("b is mapped more than once"),
+            "{error}"
+        );
+        let valid = mapping(&format!(r#"{all_but_b}, "b": {{ "byte": 1, "bit": 0 }}"#));
+        assert!(serde_json::from_str::<ControllerLayouts>(&valid).is_ok());
+    }
+
+    #[test]
+    pub fn test_joypad_register_ports() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Any": { "source": "joypad_register" },
+                    "2P": {
+                        "ports": [
+                            { "source": "joypad_register", "port": 1 },
+                            { "source": "joypad_register", "port": 2 }
+                        ]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let any = joypad(&layouts.layouts["Any"].ports[0]);
+        assert_eq!(
+            (any.address_low, any.address_high),
+            (AddressExpr::from(0xF90718), AddressExpr::from(0xF90719))
+        );
+        let second = joypad(&layouts.layouts["2P"].ports[1]);
+        assert_eq!(
+            (second.address_low, second.address_high),
+            (AddressExpr::from(0xF9071A), AddressExpr::from(0xF9071B))
+        );
+
+        for invalid in [
+            r#"{ "source": "joypad_register", "port": 5 }"#,
+            r#"{ "source": "joypad_register", "address_low": "F50010" }"#,
+            r#"{ "device": "mouse", "source": "joypad_register" }"#,
+        ] {
+            let json = format!(r#"{{ "layouts": {{ "Bad": {invalid} }} }}"#);
+            assert!(
+                serde_json::from_str::<ControllerLayouts>(&json).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_detects_joypad_register_reads() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Registers": { "source": "joypad_register", "port": 4 },
+                    "Raw": { "address_low": "F90718", "address_high": "F90719" },
+                    "Bank": { "address_low": "F9:0700+18", "address_high": "F50011" },
+                    "Memory": { "address_low": "F50010", "address_high": "F50011" },
+                    "Pointer": { "address_low": "[F50010]", "address_high": "[F50010]+1" },
+                    "Mode": {
+                        "selector": "F50998",
+                        "modes": { "0E": { "address_low": "F9071A", "address_high": "F9071B" } }
+                    },
+                    "Default mode": {
+                        "selector": "F50998",
+                        "modes": { "0E": { "address_low": "F50010", "address_high": "F50011" } },
+                        "default": { "address_low": "F90718", "address_high": "F90719" }
+                    },
+                    "Memory modes": {
+                        "selector": "F50998",
+                        "modes": { "0E": { "address_low": "F50010", "address_high": "F50011" } }
+                    },
+                    "2P": {
+                        "ports": [
+                            { "address_low": "F50010", "address_high": "F50011" },
+                            { "source": "joypad_register", "port": 2 }
+                        ]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let reads_registers = |name: &str| layouts.layouts[name].reads_joypad_registers();
+        for name in ["Registers", "Raw", "Bank", "Mode", "Default mode", "2P"] {
+            assert!(reads_registers(name), "{name}");
+        }
+        for name in ["Memory", "Pointer", "Memory modes"] {
+            assert!(!reads_registers(name), "{name}");
+        }
+    }
+
+    #[test]
+    pub fn test_mouse_ports() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Report": { "device": "mouse", "report": "F50100" },
+                    "Mixed": {
+                        "ports": [
+                            { "address_low": "F50010", "address_high": "F50011" },
+                            { "device": "mouse", "buttons": "F50020", "delta_x": "F50022", "delta_y": "F50021" }
+                        ]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        // Right button, medium sensitivity, 5 up and 3 right
+        let mut memory = FakeMemory::new([
+            (0xF50101, 0x91),
+            (0xF50102, 0x85),
+            (0xF50103, 0x03),
+            (0xF50020, 0x41),
+            (0xF50021, 0x02),
+            (0xF50022, 0x81),
+        ]);
+        let report = layouts.layouts["Report"].pushed(&mut memory).unwrap();
+        let expected = MouseState {
+            left: false,
+            right: true,
+            sensitivity: 1,
+            delta_x: 3,
+            delta_y: -5,
+        };
+        assert_eq!(report, vec![PortState::Mouse(expected)]);
+        assert_eq!(expected.to_report(), [0x00, 0x91, 0x85, 0x03]);
+
+        let mixed = layouts.layouts["Mixed"].pushed(&mut memory).unwrap();
+        assert_eq!(mixed[0], PortState::Joypad(ButtonState::default()));
+        let PortState::Mouse(mouse) = mixed[1] else {
+            panic!("expected a mouse state");
+        };
+        assert!(mouse.left && !mouse.right);
+        assert_eq!((mouse.delta_x, mouse.delta_y), (-1, 2));
+
+        let incomplete = r#"{ "layouts": { "Bad": { "device": "mouse", "buttons": "F50020" } } }"#;
+        assert!(serde_json::from_str::<ControllerLayouts>(incomplete).is_err());
+    }
+
+    #[test]
+    pub fn test_light_gun_ports() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Scope": { "device": "lightgun", "buttons": "F50200", "x": "F50202", "y": "F50204" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        // Trigger and turbo held, aiming at the middle of the screen
+        let mut memory = FakeMemory::new([
+            (0xF50200, 0xA0),
+            (0xF50202, 0x80),
+            (0xF50204, 0x70),
+            (0xF50205, 0xFE),
+        ]);
+        let states = layouts.layouts["Scope"].pushed(&mut memory).unwrap();
+        let PortState::LightGun(gun) = states[0] else {
+            panic!("expected a light gun state");
+        };
+        assert!(gun.trigger && gun.turbo && !gun.cursor && !gun.pause);
+        assert_eq!((gun.x, gun.y), (0x80, 0x70), "latches are 9 bits");
+        assert_eq!(gun.normalized_position(), Some((0.5, 0.5)));
+        assert_eq!(
+            LightGunState::from_bytes(0xA0, [0x80, 0], [0x70, 0]).to_bytes(),
+            [0xA0, 0x80, 0, 0x70, 0]
+        );
+
+        let offscreen = LightGunState::from_bytes(0x02, [0x80, 0], [0x70, 0]);
+        assert_eq!(offscreen.normalized_position(), None);
+    }
+
+    #[test]
+    pub fn test_selector_ports() {
+        let layouts: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Modes": {
+                        "selector": "F50998",
+                        "modes": {
+                            "07": { "address_low": "F50010", "address_high": "F50011" },
+                            "0E": { "address_low": "F50020", "address_high": "F50021" }
+                        }
+                    },
+                    "With default": {
+                        "selector": "F50998",
+                        "modes": { "07": { "address_low": "F50010", "address_high": "F50011" } },
+                        "default": { "address_low": "F50020", "address_high": "F50021" }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        // B held in the gameplay copy, A in the menu copy
+        let mut memory = FakeMemory::new([(0xF50998, 0x07), (0xF50011, 0x80), (0xF50020, 0x80)]);
+        let mut pressed = |layout: &str, mode: u8| -> Vec<Pressed> {
+            memory.bytes.insert(0xF50998, mode);
+            let states = layouts.layouts[layout].pushed(&mut memory).unwrap();
+            states[0].joypad().unwrap().iter().collect()
+        };
+        assert_eq!(pressed("Modes", 0x07), vec![Pressed::B]);
+        assert_eq!(pressed("Modes", 0x0E), vec![Pressed::A]);
+        assert_eq!(pressed("Modes", 0x01), vec![]);
+        assert_eq!(pressed("With default", 0x01), vec![Pressed::A]);
+
+        let invalid = r#"{ "layouts": { "Bad": { "selector": "F50998", "modes": { "XY": { "address_low": "F50010", "address_high": "F50011" } } } } }"#;
+        assert!(serde_json::from_str::<ControllerLayouts>(invalid).is_err());
+    }
+
+    #[test]
+    pub fn test_select_layout_for_rom() {
+        let available_addresses: ControllerLayouts = serde_json::from_str(
+            r#"{
+                "layouts": {
+                    "Default": { "source": "joypad_register" },
+                    "Super Metroid": { "address_low": "F5008B", "address_high": "F5008C", "rom_title": "SUPER METROID" },
+                    "Super Metroid (JU)": { "address_low": "F5008B", "address_high": "F5008C", "rom_title": "SUPER METROID", "checksum": "F8DF" },
+                    "Super Mario World": { "address_low": "F50DA4", "address_high": "F50DA2", "checksum": "A0DA" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut available_layouts: Vec<String> =
+            available_addresses.layouts.keys().cloned().collect();
+        available_layouts.sort();
+        let mut controller_data = ControllerData {
+            layout_name: String::from("Default"),
+            current_layout: available_addresses.layouts["Default"].clone(),
+            available_addresses,
+            available_layouts,
+            current_layout_index: 0,
+            configured_layout_index: 0,
+            last_rom: None,
+        };
+        let header = |title: &str, checksum: u16| RomHeader {
+            title: title.to_string(),
+            checksum,
+            region: 1,
+        };
+
+        // Unmatched on the first attach, back to the configured layout
+        controller_data.get_next_layout();
+        assert!(controller_data.select_layout_for_rom(&header("F-ZERO", 0x0000)));
+        assert_eq!(controller_data.layout_name, "Default");
+
+        assert!(controller_data.select_layout_for_rom(&header("SUPER METROID", 0xF8DF)));
+        assert_eq!(controller_data.layout_name, "Super Metroid (JU)");
+        assert!(controller_data.select_layout_for_rom(&header("SUPER METROID", 0x1234)));
+        assert_eq!(controller_data.layout_name, "Super Metroid");
+        assert!(controller_data.select_layout_for_rom(&header("SUPER MARIOWORLD", 0xA0DA)));
+        assert_eq!(controller_data.layout_name, "Super Mario World");
+        // A layout picked by hand survives reconnections to the same ROM
+        controller_data.get_next_layout();
+        assert!(!controller_data.select_layout_for_rom(&header("SUPER MARIOWORLD", 0xA0DA)));
+        assert_eq!(controller_data.layout_name, "Super Metroid");
+        // Another ROM matching no layout goes back to the configured layout
+        assert!(controller_data.select_layout_for_rom(&header("F-ZERO", 0x0000)));
+        assert_eq!(controller_data.layout_name, "Default");
+        controller_data.get_next_layout();
+        assert!(!controller_data.select_layout_for_rom(&header("F-ZERO", 0x0000)));
+        assert_eq!(controller_data.layout_name, "Super Mario World");
+    }
+
     #[test]
     pub fn test_controller_data() {
         let config = ControllerConfig {
@@ -50,22 +611,22 @@ mod tests {
 
         assert_eq!(controller_data.available_layouts, expected_layouts_name);
 
-        let expected_low_address: u32 = 0xF500F2;
-        let expected_high_address: u32 = 0xF500F0;
+        let expected_low_address = AddressExpr::from(0xF500F2);
+        let expected_high_address = AddressExpr::from(0xF500F0);
 
         assert_eq!(
             expected_low_address,
-            controller_data.current_addresses.address_low
+            joypad(&controller_data.current_layout.ports[0]).address_low
         );
         assert_eq!(
             expected_high_address,
-            controller_data.current_addresses.address_high
+            joypad(&controller_data.current_layout.ports[0]).address_high
         );
 
         let expected_next_index = controller_data.current_layout_index + 1;
         let expected_next_layout = controller_data.available_layouts[expected_next_index].clone();
-        let expected_low_address: u32 = 0xF90718;
-        let expected_high_address: u32 = 0xF90719;
+        let expected_low_address = AddressExpr::from(0xF90718);
+        let expected_high_address = AddressExpr::from(0xF90719);
         controller_data.get_next_layout();
         assert_eq!(expected_next_index, controller_data.current_layout_index);
         assert_eq!(
@@ -74,11 +635,11 @@ mod tests {
         );
         assert_eq!(
             expected_low_address,
-            controller_data.current_addresses.address_low
+            joypad(&controller_data.current_layout.ports[0]).address_low
         );
         assert_eq!(
             expected_high_address,
-            controller_data.current_addresses.address_high
+            joypad(&controller_data.current_layout.ports[0]).address_high
         );
     }
 }
@@ -0,0 +1,302 @@
+use serde::{Deserialize, Deserializer};
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::input_source::MemoryReader;
+
+/// usb2snes address of the start of the SNES work RAM (`$7E0000`).
+const WRAM: u32 = 0xF50000;
+const WRAM_SIZE: u32 = 0x20000;
+/// Size of the work RAM mirror at the start of the system banks.
+const WRAM_MIRROR_SIZE: u32 = 0x2000;
+const ADDRESS_MASK: u32 = 0xFFFFFF;
+
+/// An address written in a layout, evaluated on every poll. Besides plain hexadecimal
+/// addresses, the grammar supports:
+/// - `[expr]`: the 16 bits pointer stored at `expr`, in the bank of `expr`
+/// - `{expr}`: the 24 bits SNES pointer stored at `expr`, work RAM being translated to usb2snes
+///   addresses
+/// - `bank:expr`: `expr` with its bank replaced by `bank`
+/// - `expr + expr`, `expr - expr` and parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressExpr {
+    Literal(u32),
+    Pointer16(Box<AddressExpr>),
+    Pointer24(Box<AddressExpr>),
+    Bank(u8, Box<AddressExpr>),
+    Add(Box<AddressExpr>, Box<AddressExpr>),
+    Sub(Box<AddressExpr>, Box<AddressExpr>),
+}
+
+impl AddressExpr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            text,
+            chars: text.char_indices().peekable(),
+        };
+        let expr = parser.sum()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((position, c)) => Err(parser.error(position, &format!("unexpected '{c}'"))),
+        }
+    }
+
+    /// Resolves the address, reading the pointers it goes through.
+    pub fn evaluate<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<u32, Box<dyn Error>> {
+        Ok(match self {
+            AddressExpr::Literal(address) => *address,
+            AddressExpr::Pointer16(expr) => {
+                let location = expr.evaluate(reader)?;
+                let [low, high] = read_bytes(reader, location)?;
+                (location & 0xFF0000) | u32::from_le_bytes([low, high, 0, 0])
+            }
+            AddressExpr::Pointer24(expr) => {
+                let location = expr.evaluate(reader)?;
+                let [low, high, bank] = read_bytes(reader, location)?;
+                snes_to_usb2snes(u32::from_le_bytes([low, high, bank, 0]))
+            }
+            AddressExpr::Bank(bank, expr) => with_bank(*bank, expr.evaluate(reader)?),
+            AddressExpr::Add(left, right) => {
+                left.evaluate(reader)?.wrapping_add(right.evaluate(reader)?) & ADDRESS_MASK
+            }
+            AddressExpr::Sub(left, right) => {
+                left.evaluate(reader)?.wrapping_sub(right.evaluate(reader)?) & ADDRESS_MASK
+            }
+        })
+    }
+}
+
+impl AddressExpr {
+    /// The address, when it can be known without reading any pointer.
+    pub fn constant(&self) -> Option<u32> {
+        match self {
+            AddressExpr::Literal(address) => Some(*address),
+            AddressExpr::Pointer16(_) | AddressExpr::Pointer24(_) => None,
+            AddressExpr::Bank(bank, expr) => Some(with_bank(*bank, expr.constant()?)),
+            AddressExpr::Add(left, right) => {
+                Some(left.constant()?.wrapping_add(right.constant()?) & ADDRESS_MASK)
+            }
+            AddressExpr::Sub(left, right) => {
+                Some(left.constant()?.wrapping_sub(right.constant()?) & ADDRESS_MASK)
+            }
+        }
+    }
+}
+
+impl From<u32> for AddressExpr {
+    fn from(address: u32) -> Self {
+        AddressExpr::Literal(address)
+    }
+}
+
+impl fmt::Display for AddressExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressExpr::Literal(address) => write!(f, "{address:06X}"),
+            AddressExpr::Pointer16(expr) => write!(f, "[{expr}]"),
+            AddressExpr::Pointer24(expr) => write!(f, "{{{expr}}}"),
+            AddressExpr::Bank(bank, expr) => write!(f, "{bank:02X}:{expr}"),
+            AddressExpr::Add(left, right) => write!(f, "({left}+{right})"),
+            AddressExpr::Sub(left, right) => write!(f, "({left}-{right})"),
+        }
+    }
+}
+
+pub fn address_expr<'de, D>(deserializer: D) -> Result<AddressExpr, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let expression = String::deserialize(deserializer)?;
+    AddressExpr::parse(&expression).map_err(Error::custom)
+}
+
+fn with_bank(bank: u8, address: u32) -> u32 {
+    (bank as u32) << 16 | (address & 0xFFFF)
+}
+
+fn read_bytes<R: MemoryReader + ?Sized, const N: usize>(
+    reader: &mut R,
+    address: u32,
+) -> Result<[u8; N], Box<dyn Error>> {
+    let bytes = reader.read_memory(address, N)?;
+    bytes
+        .get(..N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| format!("short pointer read at {address:06X}").into())
+}
+
+/// Translates a SNES bus address to the usb2snes address space. Only the work RAM and its
+/// mirror in the system banks are translated, other addresses are returned unchanged.
+fn snes_to_usb2snes(address: u32) -> u32 {
+    let bank = address >> 16;
+    let offset = address & 0xFFFF;
+    match bank {
+        0x7E | 0x7F => WRAM + (address - 0x7E0000) % WRAM_SIZE,
+        0x00..=0x3F | 0x80..=0xBF if offset < WRAM_MIRROR_SIZE => WRAM + offset,
+        _ => address,
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<(usize, char)> {
+        while let Some((_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+        self.chars.peek().copied()
+    }
+
+    fn error(&self, position: usize, message: &str) -> String {
+        format!(
+            "{message} at position {position} in address '{}'",
+            self.text
+        )
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some((_, c)) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            Some((position, c)) => {
+                Err(self.error(position, &format!("expected '{expected}', found '{c}'")))
+            }
+            None => Err(self.error(self.text.len(), &format!("expected '{expected}'"))),
+        }
+    }
+
+    fn sum(&mut self) -> Result<AddressExpr, String> {
+        let mut expr = self.operand()?;
+        while let Some((_, c @ ('+' | '-'))) = self.peek() {
+            self.chars.next();
+            let right = Box::new(self.operand()?);
+            expr = match c {
+                '+' => AddressExpr::Add(Box::new(expr), right),
+                _ => AddressExpr::Sub(Box::new(expr), right),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn operand(&mut self) -> Result<AddressExpr, String> {
+        match self.peek() {
+            Some((_, '[')) => {
+                self.chars.next();
+                let expr = self.sum()?;
+                self.expect(']')?;
+                Ok(AddressExpr::Pointer16(Box::new(expr)))
+            }
+            Some((_, '{')) => {
+                self.chars.next();
+                let expr = self.sum()?;
+                self.expect('}')?;
+                Ok(AddressExpr::Pointer24(Box::new(expr)))
+            }
+            Some((_, '(')) => {
+                self.chars.next();
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some((position, c)) if c.is_ascii_hexdigit() => {
+                let value = self.number(position)?;
+                if let Some((_, ':')) = self.peek() {
+                    self.chars.next();
+                    let bank = u8::try_from(value).map_err(|_| {
+                        self.error(position, &format!("bank {value:X} out of range"))
+                    })?;
+                    return Ok(AddressExpr::Bank(bank, Box::new(self.operand()?)));
+                }
+                Ok(AddressExpr::Literal(value))
+            }
+            Some((position, c)) => Err(self.error(position, &format!("unexpected '{c}'"))),
+            None => Err(self.error(self.text.len(), "missing address")),
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<u32, String> {
+        let mut end = start;
+        while let Some(&(position, c)) = self.chars.peek() {
+            if !c.is_ascii_hexdigit() {
+                break;
+            }
+            end = position + c.len_utf8();
+            self.chars.next();
+        }
+        u32::from_str_radix(&self.text[start..end], 16)
+            .ok()
+            .filter(|&value| value <= ADDRESS_MASK)
+            .ok_or_else(|| self.error(start, "address out of range"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_source::fake_memory::FakeMemory;
+
+    #[test]
+    fn parses_and_evaluates_pointer_expressions() {
+        let mut memory = FakeMemory::new([
+            // 16 bits pointer to $1234 in WRAM
+            (0xF50010, 0x34),
+            (0xF50011, 0x12),
+            // 24 bits pointer to $7F0100
+            (0xF50020, 0x00),
+            (0xF50021, 0x01),
+            (0xF50022, 0x7F),
+            // 24 bits pointer to the WRAM mirror of bank $80
+            (0xF50030, 0x40),
+            (0xF50031, 0x00),
+            (0xF50032, 0x80),
+        ]);
+        let evaluate = |text: &str, memory: &mut FakeMemory| {
+            AddressExpr::parse(text).unwrap().evaluate(memory).unwrap()
+        };
+
+        assert_eq!(evaluate("F50DA4", &mut memory), 0xF50DA4);
+        assert_eq!(evaluate("[F50010]+4", &mut memory), 0xF51238);
+        assert_eq!(evaluate(" [ F50010 ] - 4 ", &mut memory), 0xF51230);
+        assert_eq!(evaluate("F6:[F50010]", &mut memory), 0xF61234);
+        assert_eq!(evaluate("{F50020}+2", &mut memory), 0xF60102);
+        assert_eq!(evaluate("{F50030}", &mut memory), 0xF50040);
+        assert_eq!(evaluate("(F50000+10)+2", &mut memory), 0xF50012);
+
+        assert_eq!(evaluate("F6:F50010", &mut memory), 0xF60010);
+
+        let constant = |text: &str| AddressExpr::parse(text).unwrap().constant();
+        assert_eq!(constant("F9:0700+18"), Some(0xF90718));
+        assert_eq!(constant("[F50010]+4"), None);
+        assert_eq!(
+            AddressExpr::parse("[F50010]+4").unwrap().to_string(),
+            "([F50010]+000004)"
+        );
+
+        for invalid in [
+            "",
+            "[F50010",
+            "F50010]",
+            "G5",
+            "100:F50010",
+            "F50010+",
+            "1000000",
+        ] {
+            assert!(AddressExpr::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
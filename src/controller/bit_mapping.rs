@@ -0,0 +1,150 @@
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::controller::{button_state::ButtonState, pressed::Pressed};
+
+/// How the two bytes read for a joypad are turned into a `ButtonState`, for games storing a
+/// remapped, inverted or partial copy of the joypad registers. The default reads the bytes as
+/// the registers themselves.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct InputDecoding {
+    /// Position of every button in the bytes read, `None` reading them in register order.
+    #[serde(default)]
+    pub mapping: Option<BitMapping>,
+    /// Set for active-low copies, where a cleared bit means the button is held.
+    #[serde(default)]
+    pub invert: bool,
+    /// Bits of the (inverted) bytes that hold inputs, the others are ignored.
+    #[serde(default = "full_mask", deserialize_with = "hex_to_u16")]
+    pub mask: u16,
+}
+
+impl Default for InputDecoding {
+    fn default() -> Self {
+        Self {
+            mapping: None,
+            invert: false,
+            mask: full_mask(),
+        }
+    }
+}
+
+fn full_mask() -> u16 {
+    0xFFFF
+}
+
+fn hex_to_u16<'de, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let hex_mask = String::deserialize(deserializer)?;
+    u16::from_str_radix(&hex_mask, 16).map_err(Error::custom)
+}
+
+impl InputDecoding {
+    /// Builds the state from the low and high bytes read for the joypad.
+    pub fn decode(&self, bytes: [u8; 2]) -> ButtonState {
+        let mut raw = u16::from_le_bytes(bytes);
+        if self.invert {
+            raw = !raw;
+        }
+        raw &= self.mask;
+
+        match self.mapping {
+            None => ButtonState::from_le_bytes(raw.to_le_bytes()),
+            Some(ref mapping) => Pressed::ALL
+                .into_iter()
+                .zip(mapping.0)
+                .filter(|(_, position)| position.is_some_and(|p| raw & p.mask() != 0))
+                .map(|(pressed, _)| pressed)
+                .collect(),
+        }
+    }
+}
+
+/// A bit of the bytes read for a joypad: `byte` 0 is read at `address_low`, 1 at
+/// `address_high`, and `bit` 7 is the most significant.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct BitPosition {
+    pub byte: u8,
+    pub bit: u8,
+}
+
+impl BitPosition {
+    fn mask(&self) -> u16 {
+        1 << (self.byte * 8 + self.bit)
+    }
+}
+
+/// Position of every button, in `Pressed::ALL` order. Written as an object with a key per
+/// skin button name, `null` for the buttons the game does not store.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "BitMappingDefinition")]
+pub struct BitMapping(pub [Option<BitPosition>; 12]);
+
+/// The entries of a mapping object in file order. Unlike a map, it keeps repeated keys so they
+/// can be reported instead of the last one silently winning.
+struct BitMappingDefinition(Vec<(String, Option<BitPosition>)>);
+
+impl<'de> Deserialize<'de> for BitMappingDefinition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = BitMappingDefinition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an object with the bit position of every button")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(BitMappingDefinition(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+impl TryFrom<BitMappingDefinition> for BitMapping {
+    type Error = String;
+
+    fn try_from(definition: BitMappingDefinition) -> Result<Self, Self::Error> {
+        let mut positions = [None; 12];
+        let mut mapped = [false; 12];
+        let mut used_bits = HashSet::new();
+        for (name, position) in definition.0 {
+            let pressed = Pressed::from_name(&name).ok_or(format!("unknown button {name}"))?;
+            let index = Pressed::ALL
+                .iter()
+                .position(|&p| p == pressed)
+                .unwrap_or_default();
+            if mapped[index] {
+                return Err(format!("{name} is mapped more than once"));
+            }
+            mapped[index] = true;
+            if let Some(position) = position {
+                if position.byte > 1 || position.bit > 7 {
+                    return Err(format!("{name} is mapped outside of the two bytes read"));
+                }
+                if !used_bits.insert(position) {
+                    return Err(format!("{name} is mapped to a bit already used"));
+                }
+                positions[index] = Some(position);
+            }
+        }
+        if let Some(index) = mapped.iter().position(|&m| !m) {
+            return Err(format!("{:?} is not mapped", Pressed::ALL[index]));
+        }
+        Ok(BitMapping(positions))
+    }
+}
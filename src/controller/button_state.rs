@@ -1,8 +1,15 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Sub};
+use std::str::FromStr;
+
 use crate::controller::buttons_iter::ButtonsIter;
+use crate::controller::pressed::Pressed;
 
 /// A `u16` backed bitfield representing a controller state according to the SNES joypad register
-/// layout.
-#[derive(Debug, Copy, Clone, Default)]
+/// layout. Written as the held buttons joined with `+`, e.g. `B+Y+Right`, or `None`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
 #[repr(transparent)]
 pub struct ButtonState(u16);
 
@@ -13,6 +20,11 @@ impl ButtonState {
         ButtonState(u16::from_le_bytes(bytes))
     }
 
+    /// Returns the state as little-endian bytes, the inverse of `from_le_bytes`.
+    pub fn to_le_bytes(self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+
     /// Provides an iterator over the buttons pressed in this `ButtonState` which returns
     /// `Option<Pressed>`.
     pub fn iter(&self) -> ButtonsIter {
@@ -21,4 +33,124 @@ impl ButtonState {
             cursor_offset: 0,
         }
     }
+
+    pub fn contains(self, pressed: Pressed) -> bool {
+        self.0 & pressed as u16 != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Buttons held in either state.
+    pub fn union(self, other: ButtonState) -> Self {
+        ButtonState(self.0 | other.0)
+    }
+
+    /// Buttons held in both states.
+    pub fn intersection(self, other: ButtonState) -> Self {
+        ButtonState(self.0 & other.0)
+    }
+
+    /// Buttons held in this state but not in `other`.
+    pub fn difference(self, other: ButtonState) -> Self {
+        ButtonState(self.0 & !other.0)
+    }
+
+    /// Buttons held now that were not held in the `previous` state.
+    pub fn just_pressed(self, previous: ButtonState) -> Self {
+        self.difference(previous)
+    }
+
+    /// Buttons held in the `previous` state that are not held anymore.
+    pub fn just_released(self, previous: ButtonState) -> Self {
+        previous.difference(self)
+    }
+}
+
+impl From<Pressed> for ButtonState {
+    fn from(pressed: Pressed) -> Self {
+        ButtonState(pressed as u16)
+    }
+}
+
+impl FromIterator<Pressed> for ButtonState {
+    fn from_iter<I: IntoIterator<Item = Pressed>>(buttons: I) -> Self {
+        buttons
+            .into_iter()
+            .fold(ButtonState::default(), |state, pressed| {
+                state.union(pressed.into())
+            })
+    }
+}
+
+impl BitOr for ButtonState {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+impl BitAnd for ButtonState {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        self.intersection(other)
+    }
+}
+
+impl Sub for ButtonState {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.difference(other)
+    }
+}
+
+impl fmt::Display for ButtonState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "None");
+        }
+        for (i, pressed) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, "+")?;
+            }
+            write!(f, "{pressed}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ButtonState {
+    type Err = String;
+
+    /// Parses the notation of `Display`, ignoring case and spaces around the names.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() || s.eq_ignore_ascii_case("none") {
+            return Ok(ButtonState::default());
+        }
+        s.split('+')
+            .map(|name| {
+                Pressed::from_name(&name.trim().to_lowercase())
+                    .ok_or_else(|| format!("unknown button '{}' in '{s}'", name.trim()))
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<String> for ButtonState {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ButtonState> for String {
+    fn from(state: ButtonState) -> Self {
+        state.to_string()
+    }
 }
@@ -1,9 +1,14 @@
-use rusb2snes::SyncClient;
 use std::error::Error;
 
 use serde::{Deserialize, Deserializer};
 
-use crate::controller::button_state::ButtonState;
+use crate::controller::{
+    address_expr::{address_expr, AddressExpr},
+    bit_mapping::InputDecoding,
+    button_state::ButtonState,
+};
+use crate::input_source::MemoryReader;
+
 pub fn hex_to_u32<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: Deserializer<'de>,
@@ -11,28 +16,120 @@ where
     use serde::de::Error;
 
     let hex_address = String::deserialize(deserializer)?;
-    u32::from_str_radix(&hex_address, 16).map_err(Error::custom)
+    parse_hex_address(&hex_address).map_err(Error::custom)
+}
+
+/// Parses an address written in hexadecimal without prefix, e.g. `F50DA4`.
+pub fn parse_hex_address(hex_address: &str) -> Result<u32, String> {
+    let address = u32::from_str_radix(hex_address, 16)
+        .map_err(|e| format!("invalid address {hex_address}: {e}"))?;
+    match address <= MAX_ADDRESS {
+        true => Ok(address),
+        false => Err(format!(
+            "address {hex_address} is outside of the usb2snes address space"
+        )),
+    }
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+/// Last address of the 24 bits usb2snes address space.
+pub const MAX_ADDRESS: u32 = 0xFFFFFF;
+
+/// Address of the `$4218` auto-joypad result register in the usb2snes CPU registers space, only
+/// mapped by the SD2SNES/FxPak Pro. The four joypads read by the auto-joypad feature follow, two
+/// bytes each, up to `$421F`.
+pub const JOYPAD_REGISTERS: u32 = 0xF90718;
+/// Number of joypads read by the auto-joypad feature.
+pub const JOYPAD_REGISTER_COUNT: u8 = 4;
+
+/// Whether `address` is one of the auto-joypad result registers.
+pub fn is_joypad_register(address: u32) -> bool {
+    (JOYPAD_REGISTERS..JOYPAD_REGISTERS + 2 * JOYPAD_REGISTER_COUNT as u32).contains(&address)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "ControllerAddressesDefinition")]
 pub struct ControllerAddresses {
-    #[serde(deserialize_with = "hex_to_u32")]
-    pub address_low: u32,
-    #[serde(deserialize_with = "hex_to_u32")]
-    pub address_high: u32,
+    pub address_low: AddressExpr,
+    pub address_high: AddressExpr,
+    pub decoding: InputDecoding,
+}
+
+#[derive(Deserialize)]
+struct ControllerAddressesDefinition {
+    #[serde(deserialize_with = "address_expr")]
+    pub address_low: AddressExpr,
+    #[serde(deserialize_with = "address_expr")]
+    pub address_high: AddressExpr,
+    #[serde(flatten)]
+    pub decoding: InputDecoding,
+}
+
+impl TryFrom<ControllerAddressesDefinition> for ControllerAddresses {
+    type Error = String;
+
+    fn try_from(definition: ControllerAddressesDefinition) -> Result<Self, Self::Error> {
+        if let (AddressExpr::Literal(low), AddressExpr::Literal(high)) =
+            (&definition.address_low, &definition.address_high)
+        {
+            // Only a bit mapping can take every button from a single byte
+            if low == high && definition.decoding.mapping.is_none() {
+                return Err(format!("address_low and address_high are both {low:06X}"));
+            }
+        }
+        Ok(ControllerAddresses {
+            address_low: definition.address_low,
+            address_high: definition.address_high,
+            decoding: definition.decoding,
+        })
+    }
 }
 
 impl ControllerAddresses {
-    pub fn pushed(&self, client: &mut SyncClient) -> Result<ButtonState, Box<dyn Error>> {
-        let base_address = std::cmp::min(self.address_low, self.address_high);
-        let offset_low = self.address_low.saturating_sub(base_address) as usize;
-        let offset_high = self.address_high.saturating_sub(base_address) as usize;
-        let read_length = offset_low.abs_diff(offset_high).saturating_add(1);
-        debug_assert!((2..256).contains(&read_length));
-        let input_bytes = client.get_address(base_address, read_length)?;
-        let button_state =
-            ButtonState::from_le_bytes([input_bytes[offset_low], input_bytes[offset_high]]);
+    /// Addresses of the auto-joypad result of joypad `register`, from 1 (`JOY1`, `$4218`) to 4
+    /// (`JOY4`, `$421E`).
+    pub fn joypad_register(register: u8) -> Result<Self, String> {
+        if !(1..=JOYPAD_REGISTER_COUNT).contains(&register) {
+            return Err(format!(
+                "joypad register {register} out of range 1-{JOYPAD_REGISTER_COUNT}"
+            ));
+        }
+        let address_low = JOYPAD_REGISTERS + 2 * (register as u32 - 1);
+        Ok(Self {
+            address_low: AddressExpr::from(address_low),
+            address_high: AddressExpr::from(address_low + 1),
+            decoding: InputDecoding::default(),
+        })
+    }
+
+    /// Whether a byte is read from the auto-joypad result registers. Addresses going through
+    /// pointers are only known once read, they are assumed to point to memory.
+    pub fn reads_joypad_registers(&self) -> bool {
+        [&self.address_low, &self.address_high]
+            .into_iter()
+            .any(|address| address.constant().is_some_and(is_joypad_register))
+    }
+
+    /// Reads the low and high bytes separately, so they can be anywhere in memory and in any
+    /// order. `ControllerLayout::read` fetches close bytes in a single range.
+    pub fn pushed<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<ButtonState, Box<dyn Error>> {
+        let address_low = self.address_low.evaluate(reader)?;
+        let address_high = self.address_high.evaluate(reader)?;
+        let bytes = [
+            read_byte(reader, address_low)?,
+            read_byte(reader, address_high)?,
+        ];
+        let button_state = self.decoding.decode(bytes);
 
         Ok(button_state)
     }
 }
+
+fn read_byte<R: MemoryReader + ?Sized>(reader: &mut R, address: u32) -> Result<u8, Box<dyn Error>> {
+    match reader.read_memory(address, 1)?.first() {
+        Some(&byte) => Ok(byte),
+        None => Err(format!("nothing read at {address:06X}").into()),
+    }
+}
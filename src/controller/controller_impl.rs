@@ -1,9 +1,15 @@
-use serde::{Deserialize, Serialize};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::path::PathBuf;
-use std::{collections::HashMap, fs};
+use std::{fmt, fs};
 
-use crate::controller::controller_addresses::ControllerAddresses;
+use crate::controller::frame_counter::{FrameCount, FrameCounter};
+use crate::controller::port::{ControllerPort, DeviceKind, PortState};
+use crate::controller::rom_header::RomHeader;
+use crate::input_source::{read_plan::read_batched, MemoryReader};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ControllerConfig {
@@ -11,9 +17,180 @@ pub struct ControllerConfig {
     pub layout: String,
 }
 
+/// The devices of every controller port displayed for a game. A layout is written either as
+/// a single port or as a `ports` list, player 1 first. Ports hold a joypad unless their
+/// `device` field says otherwise.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "Map<String, Value>")]
+pub struct ControllerLayout {
+    pub ports: Vec<ControllerPort>,
+    /// Title of the ROM header of the game, to select the layout automatically.
+    pub rom_title: Option<String>,
+    /// Checksum of the ROM header of the game, to select the layout automatically.
+    pub checksum: Option<u16>,
+    /// In-game frame counter read along with the inputs, to tag every poll with its frame.
+    pub frame_counter: Option<FrameCounter>,
+}
+
+/// The state of every port of a layout, read in a single poll.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayoutState {
+    /// Value of the frame counter, for layouts that define one.
+    pub frame: Option<FrameCount>,
+    pub ports: Vec<PortState>,
+}
+
+impl TryFrom<Map<String, Value>> for ControllerLayout {
+    type Error = String;
+
+    fn try_from(mut definition: Map<String, Value>) -> Result<Self, Self::Error> {
+        let rom_title = match definition.remove("rom_title") {
+            Some(title) => Some(serde_json::from_value(title).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let checksum = match definition.remove("checksum") {
+            Some(Value::String(checksum)) => Some(
+                u16::from_str_radix(&checksum, 16)
+                    .map_err(|e| format!("invalid checksum {checksum}: {e}"))?,
+            ),
+            Some(checksum) => return Err(format!("invalid checksum {checksum}")),
+            None => None,
+        };
+        let frame_counter = match definition.remove("frame_counter") {
+            Some(counter) => Some(serde_json::from_value(counter).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let ports = match definition.remove("ports") {
+            Some(ports) => serde_json::from_value(ports).map_err(|e| e.to_string())?,
+            None => vec![ControllerPort::try_from(definition)?],
+        };
+        Ok(ControllerLayout {
+            ports,
+            rom_title,
+            checksum,
+            frame_counter,
+        })
+    }
+}
+
+impl ControllerLayout {
+    /// Whether a port reads the auto-joypad result registers, which only some devices expose.
+    pub fn reads_joypad_registers(&self) -> bool {
+        self.ports
+            .iter()
+            .any(ControllerPort::reads_joypad_registers)
+    }
+
+    /// Reads the state of every port, in order.
+    pub fn pushed<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Vec<PortState>, Box<dyn Error>> {
+        self.ports.iter().map(|port| port.read(reader)).collect()
+    }
+
+    /// Reads the frame counter, if any, and every port, batching the reads of every address
+    /// in as few requests as possible.
+    pub fn read<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<LayoutState, Box<dyn Error>> {
+        read_batched(reader, |reader| {
+            let frame = match self.frame_counter {
+                Some(ref counter) => Some(counter.read(reader)?),
+                None => None,
+            };
+            Ok(LayoutState {
+                frame,
+                ports: self.pushed(reader)?,
+            })
+        })
+    }
+
+    /// How well the layout matches the running ROM: the number of header fields matching,
+    /// `None` if the layout has no header fields or one of them differs.
+    fn rom_match(&self, header: &RomHeader) -> Option<usize> {
+        let title = self.rom_title.as_ref().map(|t| t.trim() == header.title);
+        let checksum = self.checksum.map(|c| c == header.checksum);
+        let fields = [title, checksum];
+        match fields.contains(&Some(false)) {
+            true => None,
+            false => Some(fields.iter().flatten().count()).filter(|&n| n > 0),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct ControllerLayouts {
-    pub layouts: HashMap<String, ControllerAddresses>,
+    pub layouts: HashMap<String, ControllerLayout>,
+}
+
+impl ControllerLayouts {
+    /// Parses a layouts file, rejecting objects with a repeated key. Layouts are parsed through
+    /// `serde_json::Value`, which would silently keep the last value of the key.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str::<UniqueKeys>(json)?;
+        serde_json::from_str(json)
+    }
+}
+
+/// Any JSON value whose objects, at any depth, have no repeated key.
+struct UniqueKeys;
+
+impl<'de> Deserialize<'de> for UniqueKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UniqueKeysVisitor)
+    }
+}
+
+struct UniqueKeysVisitor;
+
+impl<'de> Visitor<'de> for UniqueKeysVisitor {
+    type Value = UniqueKeys;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_unit<E>(self) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UniqueKeys, A::Error> {
+        while seq.next_element::<UniqueKeys>()?.is_some() {}
+        Ok(UniqueKeys)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueKeys, A::Error> {
+        let mut keys = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            map.next_value::<UniqueKeys>()?;
+            if !keys.insert(key.clone()) {
+                return Err(de::Error::custom(format!("duplicate key {key}")));
+            }
+        }
+        Ok(UniqueKeys)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -21,8 +198,13 @@ pub struct ControllerData {
     pub layout_name: String,
     pub available_addresses: ControllerLayouts,
     pub available_layouts: Vec<String>,
-    pub current_addresses: ControllerAddresses,
+    pub current_layout: ControllerLayout,
     pub current_layout_index: usize,
+    /// Index of the layout of the settings file, used when the running ROM matches no layout.
+    pub configured_layout_index: usize,
+    /// Title and checksum of the last ROM header checked, so reattaching to the same ROM keeps
+    /// a layout picked by hand.
+    pub last_rom: Option<(String, u16)>,
 }
 
 impl ControllerData {
@@ -30,12 +212,17 @@ impl ControllerData {
         // get path of layouts json from config file
         let config_data = fs::read_to_string(&config.input_config_path)?;
 
-        let available_addresses: ControllerLayouts =
-            serde_json::from_str(&config_data)?;
+        let available_addresses = ControllerLayouts::from_json(&config_data)?;
+        if let Some((name, _)) = available_addresses
+            .layouts
+            .iter()
+            .find(|(_, layout)| layout.ports.is_empty())
+        {
+            return Err(format!("Layout {name} has no ports").into());
+        }
 
-        let mut available_layouts: Vec<String> = available_addresses
-            .layouts.keys().cloned()
-            .collect();
+        let mut available_layouts: Vec<String> =
+            available_addresses.layouts.keys().cloned().collect();
         available_layouts.sort();
 
         let current_layout_index = available_layouts
@@ -43,22 +230,63 @@ impl ControllerData {
             .position(|n| n == &config.layout)
             .ok_or("Layout index not found")?;
 
-        let current_addresses = available_addresses.layouts[&config.layout];
+        let current_layout = available_addresses.layouts[&config.layout].clone();
 
         Ok(ControllerData {
             layout_name: config.layout.clone(),
             available_addresses,
             available_layouts,
             current_layout_index,
-            current_addresses,
+            current_layout,
+            configured_layout_index: current_layout_index,
+            last_rom: None,
+        })
+    }
+
+    /// Name of a layout with a port holding `device`, if any.
+    pub fn layout_with_device(&self, device: DeviceKind) -> Option<&String> {
+        self.available_layouts.iter().find(|name| {
+            self.available_addresses.layouts[*name]
+                .ports
+                .iter()
+                .any(|port| port.device() == device)
         })
     }
 
+    /// Switches to the layout matching the header of the running ROM best, or to the configured
+    /// layout when none matches. The same ROM attached again keeps the current layout.
+    /// Returns whether the layout changed.
+    pub fn select_layout_for_rom(&mut self, header: &RomHeader) -> bool {
+        let best = self
+            .available_layouts
+            .iter()
+            .enumerate()
+            .filter_map(|(index, name)| {
+                let score = self.available_addresses.layouts[name].rom_match(header)?;
+                Some((score, index))
+            })
+            // Prefer the first layout by name on ties
+            .max_by_key(|&(score, index)| (score, std::cmp::Reverse(index)));
+        let rom = Some((header.title.clone(), header.checksum));
+        if self.last_rom == rom {
+            return false;
+        }
+        self.last_rom = rom;
+        let index = best.map_or(self.configured_layout_index, |(_, index)| index);
+        if index == self.current_layout_index {
+            return false;
+        }
+        self.current_layout_index = index;
+        self.layout_name = self.available_layouts[index].clone();
+        self.current_layout = self.available_addresses.layouts[&self.layout_name].clone();
+        true
+    }
+
     pub fn get_next_layout(&mut self) {
         // add one and modulo to loop on the list
         self.current_layout_index = (self.current_layout_index + 1) % self.available_layouts.len();
         self.layout_name = self.available_layouts[self.current_layout_index].clone();
-        self.current_addresses = self.available_addresses.layouts[&self.layout_name];
+        self.current_layout = self.available_addresses.layouts[&self.layout_name].clone();
     }
 
     pub fn get_prev_layout(&mut self) {
@@ -66,6 +294,6 @@ impl ControllerData {
         let len = self.available_layouts.len();
         self.current_layout_index = (self.current_layout_index + len - 1) % len;
         self.layout_name = self.available_layouts[self.current_layout_index].clone();
-        self.current_addresses = self.available_addresses.layouts[&self.layout_name];
+        self.current_layout = self.available_addresses.layouts[&self.layout_name].clone();
     }
 }
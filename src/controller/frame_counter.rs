@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use std::error::Error;
+
+use crate::controller::address_expr::{address_expr, AddressExpr};
+use crate::input_source::MemoryReader;
+
+/// In-game frame counter of a layout, incremented once per frame by the game and wrapping
+/// around at its size.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, try_from = "FrameCounterDefinition")]
+pub struct FrameCounter {
+    address: AddressExpr,
+    bytes: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrameCounterDefinition {
+    #[serde(deserialize_with = "address_expr")]
+    address: AddressExpr,
+    #[serde(default = "default_bytes")]
+    bytes: u8,
+}
+
+fn default_bytes() -> u8 {
+    1
+}
+
+impl TryFrom<FrameCounterDefinition> for FrameCounter {
+    type Error = String;
+
+    fn try_from(definition: FrameCounterDefinition) -> Result<Self, Self::Error> {
+        match definition.bytes {
+            1..=4 => Ok(FrameCounter {
+                address: definition.address,
+                bytes: definition.bytes,
+            }),
+            bytes => Err(format!("frame counters are 1 to 4 bytes, not {bytes}")),
+        }
+    }
+}
+
+impl FrameCounter {
+    pub fn read<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<FrameCount, Box<dyn Error>> {
+        let address = self.address.evaluate(reader)?;
+        let bytes = reader.read_memory(address, self.bytes as usize)?;
+        if bytes.len() < self.bytes as usize {
+            return Err(format!("short frame counter read at {address:06X}").into());
+        }
+        let mut value = [0; 4];
+        value[..self.bytes as usize].copy_from_slice(&bytes[..self.bytes as usize]);
+        Ok(FrameCount {
+            value: u32::from_le_bytes(value),
+            bytes: self.bytes,
+        })
+    }
+}
+
+/// A value read from a `FrameCounter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCount {
+    pub value: u32,
+    /// Size of the counter, it wraps around to 0 after `0xFF`, `0xFFFF`...
+    pub bytes: u8,
+}
+
+impl FrameCount {
+    fn mask(self) -> u32 {
+        match self.bytes {
+            4.. => u32::MAX,
+            bytes => (1 << (bytes * 8)) - 1,
+        }
+    }
+
+    /// Frames elapsed since `previous`, accounting for the counter wrapping around. `None`
+    /// when the counter went back by more than half its range, as when the game resets it.
+    pub fn frames_since(self, previous: FrameCount) -> Option<u32> {
+        let mask = self.mask();
+        let elapsed = self.value.wrapping_sub(previous.value) & mask;
+        (self.bytes == previous.bytes && elapsed <= mask / 2).then_some(elapsed)
+    }
+}
@@ -0,0 +1,113 @@
+use serde::Deserialize;
+use std::error::Error;
+
+use crate::controller::controller_addresses::hex_to_u32;
+use crate::input_source::MemoryReader;
+
+/// Width of the picture in H latch units, used to scale the aimed position.
+pub const SCREEN_WIDTH: f32 = 256.0;
+/// Height of the picture in V latch units.
+pub const SCREEN_HEIGHT: f32 = 224.0;
+
+/// State of a Super Scope or Justifier light gun: its buttons and the position latched by the
+/// PPU when the gun saw the beam.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LightGunState {
+    pub trigger: bool,
+    pub cursor: bool,
+    pub turbo: bool,
+    pub pause: bool,
+    /// Set when the gun does not point at the screen, `x` and `y` are then meaningless.
+    pub offscreen: bool,
+    /// Horizontal position, from the H counter latch (`OPHCT`).
+    pub x: u16,
+    /// Vertical position, from the V counter latch (`OPVCT`).
+    pub y: u16,
+}
+
+impl LightGunState {
+    const TRIGGER: u8 = 0x80;
+    const CURSOR: u8 = 0x40;
+    const TURBO: u8 = 0x20;
+    const PAUSE: u8 = 0x10;
+    const OFFSCREEN: u8 = 0x02;
+    /// The latches are 9 bits counters.
+    const LATCH_MASK: u16 = 0x01FF;
+
+    /// Builds a state from a buttons byte in the Super Scope report format and the little-endian
+    /// H and V latches.
+    pub fn from_bytes(buttons: u8, x: [u8; 2], y: [u8; 2]) -> Self {
+        Self {
+            trigger: buttons & Self::TRIGGER != 0,
+            cursor: buttons & Self::CURSOR != 0,
+            turbo: buttons & Self::TURBO != 0,
+            pause: buttons & Self::PAUSE != 0,
+            offscreen: buttons & Self::OFFSCREEN != 0,
+            x: u16::from_le_bytes(x) & Self::LATCH_MASK,
+            y: u16::from_le_bytes(y) & Self::LATCH_MASK,
+        }
+    }
+
+    /// Inverse of `from_bytes`, as a buttons byte followed by both latches.
+    pub fn to_bytes(self) -> [u8; 5] {
+        let buttons = [
+            (self.trigger, Self::TRIGGER),
+            (self.cursor, Self::CURSOR),
+            (self.turbo, Self::TURBO),
+            (self.pause, Self::PAUSE),
+            (self.offscreen, Self::OFFSCREEN),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |byte, (_, bit)| byte | bit);
+        let [x_low, x_high] = self.x.to_le_bytes();
+        let [y_low, y_high] = self.y.to_le_bytes();
+        [buttons, x_low, x_high, y_low, y_high]
+    }
+
+    /// Aimed position scaled to 0.0..=1.0 on both axes, `None` when off screen.
+    pub fn normalized_position(&self) -> Option<(f32, f32)> {
+        if self.offscreen {
+            return None;
+        }
+        Some((
+            (self.x as f32 / SCREEN_WIDTH).clamp(0.0, 1.0),
+            (self.y as f32 / SCREEN_HEIGHT).clamp(0.0, 1.0),
+        ))
+    }
+}
+
+/// Where a layout finds the light gun data: a buttons byte in the Super Scope report format,
+/// and the 16 bits H and V latches stored by the game.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct LightGunAddresses {
+    #[serde(deserialize_with = "hex_to_u32")]
+    pub buttons: u32,
+    #[serde(deserialize_with = "hex_to_u32")]
+    pub x: u32,
+    #[serde(deserialize_with = "hex_to_u32")]
+    pub y: u32,
+}
+
+impl LightGunAddresses {
+    pub fn read<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<LightGunState, Box<dyn Error>> {
+        let mut bytes = |address: u32, length: usize| -> Result<Vec<u8>, Box<dyn Error>> {
+            let bytes = reader.read_memory(address, length)?;
+            match bytes.len() >= length {
+                true => Ok(bytes),
+                false => Err("short light gun read".into()),
+            }
+        };
+        let buttons = bytes(self.buttons, 1)?[0];
+        let x = bytes(self.x, 2)?;
+        let y = bytes(self.y, 2)?;
+        Ok(LightGunState::from_bytes(
+            buttons,
+            [x[0], x[1]],
+            [y[0], y[1]],
+        ))
+    }
+}
@@ -0,0 +1,169 @@
+use serde::Deserialize;
+use std::error::Error;
+
+use crate::controller::controller_addresses::parse_hex_address;
+use crate::input_source::MemoryReader;
+
+/// State of a SNES Mouse, as reported by its 32-bit serial report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseState {
+    pub left: bool,
+    pub right: bool,
+    /// Sensitivity setting of the mouse, from 0 (slow) to 2 (fast).
+    pub sensitivity: u8,
+    /// Horizontal motion since the previous report, positive to the right.
+    pub delta_x: i8,
+    /// Vertical motion since the previous report, positive downward.
+    pub delta_y: i8,
+}
+
+impl MouseState {
+    const RIGHT_BUTTON: u8 = 0x80;
+    const LEFT_BUTTON: u8 = 0x40;
+    const SENSITIVITY_SHIFT: u8 = 4;
+    /// Low nibble of the second report byte, identifying a mouse.
+    const SIGNATURE: u8 = 0x01;
+    /// Direction bit of the motion bytes, set for up and left.
+    const DIRECTION: u8 = 0x80;
+
+    /// Builds a state from the serial report bytes, in the order they are shifted out: a
+    /// zero byte, the buttons and sensitivity byte, then the Y and X motion bytes in sign and
+    /// magnitude form.
+    pub fn from_report(report: [u8; 4]) -> Self {
+        Self::from_bytes(report[1], report[2], report[3])
+    }
+
+    /// Inverse of `from_report`.
+    pub fn to_report(self) -> [u8; 4] {
+        let mut buttons = Self::SIGNATURE | (self.sensitivity & 0x03) << Self::SENSITIVITY_SHIFT;
+        if self.right {
+            buttons |= Self::RIGHT_BUTTON;
+        }
+        if self.left {
+            buttons |= Self::LEFT_BUTTON;
+        }
+        [
+            0,
+            buttons,
+            to_sign_magnitude(self.delta_y),
+            to_sign_magnitude(self.delta_x),
+        ]
+    }
+
+    fn from_bytes(buttons: u8, delta_y: u8, delta_x: u8) -> Self {
+        Self {
+            left: buttons & Self::LEFT_BUTTON != 0,
+            right: buttons & Self::RIGHT_BUTTON != 0,
+            sensitivity: (buttons >> Self::SENSITIVITY_SHIFT) & 0x03,
+            delta_x: from_sign_magnitude(delta_x),
+            delta_y: from_sign_magnitude(delta_y),
+        }
+    }
+
+    /// Motion scaled to -1.0..=1.0, `full_scale` being the delta drawn at the edge of the
+    /// motion indicator.
+    pub fn normalized_motion(&self, full_scale: f32) -> (f32, f32) {
+        let scale = |delta: i8| (delta as f32 / full_scale).clamp(-1.0, 1.0);
+        (scale(self.delta_x), scale(self.delta_y))
+    }
+}
+
+fn from_sign_magnitude(byte: u8) -> i8 {
+    let magnitude = (byte & !MouseState::DIRECTION) as i8;
+    match byte & MouseState::DIRECTION {
+        0 => magnitude,
+        _ => -magnitude,
+    }
+}
+
+fn to_sign_magnitude(delta: i8) -> u8 {
+    let magnitude = delta.unsigned_abs().min(0x7F);
+    match delta < 0 {
+        true => MouseState::DIRECTION | magnitude,
+        false => magnitude,
+    }
+}
+
+/// Where a layout finds the mouse data: either a copy of the whole 4 bytes serial report, or
+/// separate buttons and motion bytes in the same format as the report.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "MouseDefinition")]
+pub enum MouseAddresses {
+    Report(u32),
+    Separate {
+        buttons: u32,
+        delta_x: u32,
+        delta_y: u32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MouseDefinition {
+    report: Option<String>,
+    buttons: Option<String>,
+    delta_x: Option<String>,
+    delta_y: Option<String>,
+}
+
+impl TryFrom<MouseDefinition> for MouseAddresses {
+    type Error = String;
+
+    fn try_from(definition: MouseDefinition) -> Result<Self, Self::Error> {
+        match definition {
+            MouseDefinition {
+                report: Some(report),
+                buttons: None,
+                delta_x: None,
+                delta_y: None,
+            } => Ok(MouseAddresses::Report(parse_hex_address(&report)?)),
+            MouseDefinition {
+                report: None,
+                buttons: Some(buttons),
+                delta_x: Some(delta_x),
+                delta_y: Some(delta_y),
+            } => Ok(MouseAddresses::Separate {
+                buttons: parse_hex_address(&buttons)?,
+                delta_x: parse_hex_address(&delta_x)?,
+                delta_y: parse_hex_address(&delta_y)?,
+            }),
+            _ => Err(String::from(
+                "a mouse needs either a report address or buttons, delta_x and delta_y addresses",
+            )),
+        }
+    }
+}
+
+impl MouseAddresses {
+    pub fn read<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<MouseState, Box<dyn Error>> {
+        let byte = |reader: &mut R, address: u32| -> Result<u8, Box<dyn Error>> {
+            reader
+                .read_memory(address, 1)?
+                .first()
+                .copied()
+                .ok_or_else(|| "empty read".into())
+        };
+        match *self {
+            MouseAddresses::Report(address) => {
+                let report = reader.read_memory(address, 4)?;
+                let report: [u8; 4] = report
+                    .get(..4)
+                    .and_then(|r| r.try_into().ok())
+                    .ok_or("short mouse report read")?;
+                Ok(MouseState::from_report(report))
+            }
+            MouseAddresses::Separate {
+                buttons,
+                delta_x,
+                delta_y,
+            } => Ok(MouseState::from_bytes(
+                byte(reader, buttons)?,
+                byte(reader, delta_y)?,
+                byte(reader, delta_x)?,
+            )),
+        }
+    }
+}
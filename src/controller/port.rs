@@ -0,0 +1,146 @@
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+use std::error::Error;
+
+use crate::controller::{
+    button_state::ButtonState,
+    controller_addresses::ControllerAddresses,
+    light_gun::{LightGunAddresses, LightGunState},
+    mouse::{MouseAddresses, MouseState},
+    selector::SelectedAddresses,
+};
+use crate::input_source::MemoryReader;
+
+/// Kind of device plugged in a controller port, from the `device` field of a layout port.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    #[default]
+    Joypad,
+    Mouse,
+    /// Super Scope or Justifier.
+    LightGun,
+}
+
+/// Where a port reads its device, from the `source` field of a layout port.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum PortSource {
+    /// Addresses given in the port, usually where the game copies the inputs in WRAM.
+    #[default]
+    Addresses,
+    /// The auto-joypad result registers, which work with any game on a SD2SNES/FxPak Pro.
+    JoypadRegister,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JoypadRegisterDefinition {
+    #[serde(default = "first_register")]
+    port: u8,
+}
+
+fn first_register() -> u8 {
+    1
+}
+
+/// Where the state of the device plugged in a controller port is read from.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "Map<String, Value>")]
+pub enum ControllerPort {
+    Joypad(ControllerAddresses),
+    /// A joypad read at addresses depending on the game mode.
+    SelectedJoypad(SelectedAddresses),
+    Mouse(MouseAddresses),
+    LightGun(LightGunAddresses),
+}
+
+impl TryFrom<Map<String, Value>> for ControllerPort {
+    type Error = String;
+
+    fn try_from(mut definition: Map<String, Value>) -> Result<Self, Self::Error> {
+        let device: DeviceKind = take_field(&mut definition, "device")?.unwrap_or_default();
+        let source: PortSource = take_field(&mut definition, "source")?.unwrap_or_default();
+        let addresses = Value::Object(definition);
+        if source == PortSource::JoypadRegister {
+            if device != DeviceKind::Joypad {
+                return Err(format!(
+                    "the joypad_register source cannot read a {device:?}"
+                ));
+            }
+            let register: JoypadRegisterDefinition =
+                serde_json::from_value(addresses).map_err(|e| e.to_string())?;
+            return ControllerAddresses::joypad_register(register.port).map(ControllerPort::Joypad);
+        }
+        let port = match device {
+            DeviceKind::Joypad if addresses.get("selector").is_some() => {
+                serde_json::from_value(addresses).map(ControllerPort::SelectedJoypad)
+            }
+            DeviceKind::Joypad => serde_json::from_value(addresses).map(ControllerPort::Joypad),
+            DeviceKind::Mouse => serde_json::from_value(addresses).map(ControllerPort::Mouse),
+            DeviceKind::LightGun => serde_json::from_value(addresses).map(ControllerPort::LightGun),
+        };
+        port.map_err(|e| format!("invalid {device:?} port: {e}"))
+    }
+}
+
+fn take_field<T: DeserializeOwned>(
+    definition: &mut Map<String, Value>,
+    key: &str,
+) -> Result<Option<T>, String> {
+    definition
+        .remove(key)
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| format!("invalid {key}: {e}"))
+}
+
+impl ControllerPort {
+    pub fn device(&self) -> DeviceKind {
+        match self {
+            ControllerPort::Joypad(_) | ControllerPort::SelectedJoypad(_) => DeviceKind::Joypad,
+            ControllerPort::Mouse(_) => DeviceKind::Mouse,
+            ControllerPort::LightGun(_) => DeviceKind::LightGun,
+        }
+    }
+
+    pub fn reads_joypad_registers(&self) -> bool {
+        match self {
+            ControllerPort::Joypad(addresses) => addresses.reads_joypad_registers(),
+            ControllerPort::SelectedJoypad(addresses) => addresses.reads_joypad_registers(),
+            ControllerPort::Mouse(_) | ControllerPort::LightGun(_) => false,
+        }
+    }
+
+    pub fn read<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<PortState, Box<dyn Error>> {
+        match self {
+            ControllerPort::Joypad(addresses) => addresses.pushed(reader).map(PortState::Joypad),
+            ControllerPort::SelectedJoypad(addresses) => {
+                addresses.pushed(reader).map(PortState::Joypad)
+            }
+            ControllerPort::Mouse(addresses) => addresses.read(reader).map(PortState::Mouse),
+            ControllerPort::LightGun(addresses) => addresses.read(reader).map(PortState::LightGun),
+        }
+    }
+}
+
+/// State of the device plugged in a controller port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    Joypad(ButtonState),
+    Mouse(MouseState),
+    LightGun(LightGunState),
+}
+
+impl PortState {
+    /// The joypad buttons held, `None` for other devices.
+    pub fn joypad(&self) -> Option<ButtonState> {
+        match self {
+            PortState::Joypad(state) => Some(*state),
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 #[repr(u16)]
 pub enum Pressed {
@@ -15,7 +17,48 @@ pub enum Pressed {
     B = 0x8000,
 }
 
+impl fmt::Display for Pressed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
 impl Pressed {
+    /// Every button, in the SNES joypad register order from the highest bit to the lowest.
+    pub const ALL: [Pressed; 12] = [
+        Pressed::B,
+        Pressed::Y,
+        Pressed::Select,
+        Pressed::Start,
+        Pressed::Up,
+        Pressed::Down,
+        Pressed::Left,
+        Pressed::Right,
+        Pressed::A,
+        Pressed::X,
+        Pressed::L,
+        Pressed::R,
+    ];
+
+    /// Parses a button from its RetroSpy skin name, e.g. `select` or `up`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "a" => Some(Pressed::A),
+            "b" => Some(Pressed::B),
+            "x" => Some(Pressed::X),
+            "y" => Some(Pressed::Y),
+            "select" => Some(Pressed::Select),
+            "start" => Some(Pressed::Start),
+            "l" => Some(Pressed::L),
+            "r" => Some(Pressed::R),
+            "up" => Some(Pressed::Up),
+            "down" => Some(Pressed::Down),
+            "left" => Some(Pressed::Left),
+            "right" => Some(Pressed::Right),
+            _ => None,
+        }
+    }
+
     /// Accepts a `u16` with a single bit set according to the SNES joypad register layout and
     /// returns `Option<Pressed>` where None represents no buttons pushed. Caller is responsible
     /// for ensuring that the value passed in is zero or a single, valid bit. Otherwise the
@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::input_source::MemoryReader;
+
+/// ROM offsets of the internal header (`$00FFC0` on the SNES bus) for LoROM and HiROM games.
+const HEADER_OFFSETS: [u32; 2] = [0x7FC0, 0xFFC0];
+const HEADER_LENGTH: usize = 0x20;
+const TITLE_LENGTH: usize = 21;
+const REGION_OFFSET: usize = 0x19;
+const COMPLEMENT_OFFSET: usize = 0x1C;
+const CHECKSUM_OFFSET: usize = 0x1E;
+
+/// The identifying fields of the internal header of the running ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomHeader {
+    /// Title, without its space padding.
+    pub title: String,
+    pub checksum: u16,
+    /// Destination code, 0 for Japan, 1 for North America...
+    pub region: u8,
+}
+
+impl RomHeader {
+    /// Reads the header of the running ROM, `None` when neither the LoROM nor the HiROM
+    /// location holds a header with a consistent checksum.
+    pub fn read<R: MemoryReader + ?Sized>(reader: &mut R) -> Result<Option<Self>, Box<dyn Error>> {
+        for offset in HEADER_OFFSETS {
+            let bytes = reader.read_memory(offset, HEADER_LENGTH)?;
+            if let Some(header) = Self::parse(&bytes) {
+                return Ok(Some(header));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Parses a header, checking the checksum against its complement.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let word = |offset: usize| {
+            let bytes = bytes.get(offset..offset + 2)?;
+            Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        let checksum = word(CHECKSUM_OFFSET)?;
+        if checksum ^ word(COMPLEMENT_OFFSET)? != 0xFFFF {
+            return None;
+        }
+        let title = String::from_utf8_lossy(&bytes[..TITLE_LENGTH])
+            .trim_end_matches([' ', '\0'])
+            .to_string();
+        Some(Self {
+            title,
+            checksum,
+            region: bytes[REGION_OFFSET],
+        })
+    }
+}
+
+impl fmt::Display for RomHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (checksum {:04X}, region {})",
+            self.title, self.checksum, self.region
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers_with_consistent_checksums() {
+        let mut bytes = b"SUPER METROID        ".to_vec();
+        bytes.extend([0x30, 0x02, 0x0C, 0x03, 0x01, 0x33, 0x00]);
+        bytes.extend([0x20, 0x07, 0xDF, 0xF8]);
+        let header = RomHeader::parse(&bytes).unwrap();
+        assert_eq!(header.title, "SUPER METROID");
+        assert_eq!(header.checksum, 0xF8DF);
+        assert_eq!(header.region, 0x01);
+        assert_eq!(
+            header.to_string(),
+            "SUPER METROID (checksum F8DF, region 1)"
+        );
+
+        bytes[COMPLEMENT_OFFSET] = 0;
+        assert_eq!(RomHeader::parse(&bytes), None);
+        assert_eq!(RomHeader::parse(&bytes[..8]), None);
+    }
+}
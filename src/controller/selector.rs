@@ -0,0 +1,71 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use crate::controller::{
+    button_state::ButtonState,
+    controller_addresses::{hex_to_u32, is_joypad_register, ControllerAddresses},
+};
+use crate::input_source::MemoryReader;
+
+/// Joypad addresses depending on the game mode, for games moving their input copy between
+/// menus, gameplay and cutscenes. Every poll reads the `selector` byte first, then the
+/// addresses of its value in `modes`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SelectedAddresses {
+    #[serde(deserialize_with = "hex_to_u32")]
+    pub selector: u32,
+    #[serde(deserialize_with = "hex_to_modes")]
+    pub modes: BTreeMap<u8, ControllerAddresses>,
+    /// Addresses of the values missing from `modes`, no buttons are shown for them otherwise.
+    #[serde(default)]
+    pub default: Option<ControllerAddresses>,
+}
+
+fn hex_to_modes<'de, D>(deserializer: D) -> Result<BTreeMap<u8, ControllerAddresses>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    BTreeMap::<String, ControllerAddresses>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(value, addresses)| {
+            let value = u8::from_str_radix(&value, 16)
+                .map_err(|e| Error::custom(format!("invalid mode {value}: {e}")))?;
+            Ok((value, addresses))
+        })
+        .collect()
+}
+
+impl SelectedAddresses {
+    /// Addresses used when the selector holds `value`.
+    pub fn addresses(&self, value: u8) -> Option<&ControllerAddresses> {
+        self.modes.get(&value).or(self.default.as_ref())
+    }
+
+    /// Whether the selector, or the addresses of a mode, are auto-joypad result registers.
+    pub fn reads_joypad_registers(&self) -> bool {
+        is_joypad_register(self.selector)
+            || self
+                .modes
+                .values()
+                .chain(&self.default)
+                .any(ControllerAddresses::reads_joypad_registers)
+    }
+
+    pub fn pushed<R: MemoryReader + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<ButtonState, Box<dyn Error>> {
+        let value = *reader
+            .read_memory(self.selector, 1)?
+            .first()
+            .ok_or("empty selector read")?;
+        match self.addresses(value) {
+            Some(addresses) => addresses.pushed(reader),
+            None => Ok(ButtonState::default()),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+pub mod connection;
+#[cfg(test)]
+pub mod fake_memory;
+#[cfg(test)]
+pub mod mock_usb2snes;
+pub mod poller;
+pub mod read_plan;
+pub mod replay;
+pub mod usb2snes;
+
+use std::{error::Error, time::Duration};
+
+use crate::controller::controller_impl::{ControllerLayout, LayoutState};
+use crate::input_source::connection::ConnectionError;
+
+/// Connection state reported by an `InputSource`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceStatus {
+    /// Nothing is connected, `connect` has to be called before polling.
+    Disconnected,
+    /// Connected to the backend, but no device is attached yet.
+    Connected,
+    /// Attached to the named device and ready to be polled.
+    Attached(String),
+}
+
+/// What a device reports about itself, through the usb2snes `Info` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub firmware: String,
+    /// Kind of device, e.g. `SD2SNES` or `Snes9x`.
+    pub kind: String,
+    /// Path of the running ROM on the device.
+    pub rom: String,
+}
+
+impl DeviceInfo {
+    /// Whether the device maps the CPU registers, such as the auto-joypad results. Only the
+    /// SD2SNES/FxPak Pro does, emulators and other devices only expose the memory.
+    ///
+    /// Written for the device types `Info` reports for the cartridge: `SD2SNES` with QUsb2Snes
+    /// (for the FxPak Pro too) and `fxpakpro` with SNI. Anything else, e.g. `RETROARCH`,
+    /// `SNES9X` or `emunw`, is taken for a device without the registers.
+    pub fn exposes_cpu_registers(&self) -> bool {
+        let kind = self.kind.to_lowercase();
+        kind.contains("sd2snes") || kind.contains("fxpak")
+    }
+}
+
+/// Read access to the SNES address space, as exposed by a backend such as usb2snes.
+pub trait MemoryReader {
+    /// Reads `length` bytes starting at `address`.
+    fn read_memory(&mut self, address: u32, length: usize) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Reads every `(address, length)` range, in a single request when the backend supports it.
+    fn read_many(&mut self, ranges: &[(u32, usize)]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        ranges
+            .iter()
+            .map(|&(address, length)| self.read_memory(address, length))
+            .collect()
+    }
+}
+
+/// Anything that can produce `LayoutState`s for the `InputViewer`: a live usb2snes connection,
+/// a replay file, a test double...
+pub trait InputSource: Send {
+    /// Establishes the connection to the backend. Called by the poller when the source is not
+    /// attached, spacing the attempts that fail.
+    fn connect(&mut self) -> Result<(), ConnectionError>;
+
+    /// Drops the connection after repeated read failures, `connect` is called again next.
+    fn disconnect(&mut self) {}
+
+    /// Devices listed by the backend on the last connection, for sources that can choose one.
+    fn devices(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Attaches to `device` on the next connection. The current connection is dropped.
+    fn select_device(&mut self, _device: &str) {}
+
+    /// Why `layout` can never be read from the attached device. Checked once when attaching
+    /// and when the layout changes, the layout is not polled while it fails.
+    fn check_layout(&self, _layout: &ControllerLayout) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Returns the current state of every controller port and the in-game frame, read according
+    /// to the given layout.
+    fn poll(&mut self, layout: &ControllerLayout) -> Result<LayoutState, Box<dyn Error>>;
+
+    fn status(&self) -> SourceStatus;
+
+    /// Firmware and running ROM of the attached device, for sources that can query it.
+    fn device_info(&self) -> Option<DeviceInfo> {
+        None
+    }
+
+    /// Human readable description of the source, used in logs.
+    fn describe(&self) -> String;
+
+    /// Direct access to the console memory, for sources attached to a console.
+    fn memory(&mut self) -> Option<&mut dyn MemoryReader> {
+        None
+    }
+
+    /// Round trip of every memory read since the previous call, for sources reading the console
+    /// memory.
+    fn take_read_times(&mut self) -> Vec<Duration> {
+        Vec::new()
+    }
+
+    /// Playback controls, for sources replaying a recorded session.
+    fn playback(&mut self) -> Option<&mut dyn Playback> {
+        None
+    }
+}
+
+/// Controls of a source replaying recorded inputs at their original timing.
+pub trait Playback {
+    fn toggle_pause(&mut self);
+
+    /// Moves the playback position forward, or backward when `backward` is set.
+    fn seek(&mut self, offset: Duration, backward: bool);
+
+    /// Pauses and moves to the next sample, or the previous one when `backward` is set.
+    fn step(&mut self, backward: bool);
+
+    /// Selects the next faster playback speed, or the next slower one when `slower` is set.
+    fn change_speed(&mut self, slower: bool);
+
+    /// Short description of the playback state, e.g. `paused 0:12.3 x2`.
+    fn state_label(&self) -> String;
+}
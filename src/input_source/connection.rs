@@ -0,0 +1,303 @@
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Delay before the second connection attempt, doubled after every failed attempt.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(250);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+/// Consecutive failed reads after which the source is disconnected and connected again.
+pub const MAX_FAILED_READS: u32 = 3;
+
+/// Why a source could not connect or read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// The usb2snes websocket (QUsb2Snes, SNI...) is not running or refused the connection.
+    WebsocketUnavailable(String),
+    /// The websocket is up, but no device is available.
+    NoDevice,
+    /// No device matches the configured name.
+    NoMatchingDevice(String),
+    /// A device was found, but attaching to it failed.
+    AttachFailed(String),
+    /// Reading the console memory failed.
+    ReadFailed(String),
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::WebsocketUnavailable(e) => {
+                write!(f, "usb2snes websocket unavailable: {e}")
+            }
+            ConnectionError::NoDevice => write!(f, "no usb2snes device available"),
+            ConnectionError::NoMatchingDevice(name) => {
+                write!(f, "no usb2snes device matching '{name}'")
+            }
+            ConnectionError::AttachFailed(e) => write!(f, "could not attach to the device: {e}"),
+            ConnectionError::ReadFailed(e) => write!(f, "could not read the inputs: {e}"),
+        }
+    }
+}
+
+impl Error for ConnectionError {}
+
+/// Connection state of the polled source, as seen by the poller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    /// Not connected. The next attempt is made at `retry_at`, `attempts` attempts failed so far.
+    Disconnected {
+        reason: Option<ConnectionError>,
+        attempts: u32,
+        retry_at: Instant,
+    },
+    /// Connection attempt number `attempt` (1 based) in progress.
+    Connecting { attempt: u32 },
+    /// Attached to `device`, reads succeed.
+    Attached { device: String },
+    /// Attached to `device`, but the last `failed_reads` reads failed.
+    Degraded {
+        device: String,
+        failed_reads: u32,
+        reason: ConnectionError,
+    },
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Disconnected {
+                reason, retry_at, ..
+            } => {
+                write!(f, "Disconnected")?;
+                if let Some(reason) = reason {
+                    write!(f, ": {reason}")?;
+                }
+                let retry_in = retry_at.saturating_duration_since(Instant::now());
+                write!(f, " (retrying in {:.1}s)", retry_in.as_secs_f32())
+            }
+            ConnectionState::Connecting { attempt } => write!(f, "Connecting (attempt {attempt})"),
+            ConnectionState::Attached { device } => write!(f, "Attached to {device}"),
+            ConnectionState::Degraded {
+                device,
+                failed_reads,
+                reason,
+            } => write!(
+                f,
+                "{device} degraded, {failed_reads} failed reads: {reason}"
+            ),
+        }
+    }
+}
+
+/// Moves through the `ConnectionState`s as attempts and reads succeed or fail, spacing
+/// attempts with an exponential backoff.
+#[derive(Debug)]
+pub struct Connection {
+    state: ConnectionState,
+    /// Connections dropped in a row without a successful read, for devices that attach fine
+    /// but can't be read.
+    dropped: u32,
+}
+
+impl Connection {
+    /// Starts disconnected, with an attempt due right away.
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: ConnectionState::Disconnected {
+                reason: None,
+                attempts: 0,
+                retry_at: now,
+            },
+            dropped: 0,
+        }
+    }
+
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    /// Moves to `Connecting` and returns true when disconnected and an attempt is due at `now`.
+    pub fn start_attempt(&mut self, now: Instant) -> bool {
+        match self.state {
+            ConnectionState::Disconnected {
+                attempts, retry_at, ..
+            } if retry_at <= now => {
+                self.state = ConnectionState::Connecting {
+                    attempt: attempts + 1,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drops the connection to attach again right away, as when switching devices.
+    pub fn reconnect(&mut self, now: Instant) {
+        self.dropped = 0;
+        self.state = ConnectionState::Disconnected {
+            reason: None,
+            attempts: 0,
+            retry_at: now,
+        };
+    }
+
+    pub fn attempt_succeeded(&mut self, device: String) {
+        self.state = ConnectionState::Attached { device };
+    }
+
+    pub fn attempt_failed(&mut self, error: ConnectionError, now: Instant) {
+        let attempts = match self.state {
+            ConnectionState::Connecting { attempt } => attempt,
+            _ => 1,
+        };
+        self.state = ConnectionState::Disconnected {
+            reason: Some(error),
+            attempts,
+            retry_at: now + retry_delay(attempts),
+        };
+    }
+
+    pub fn read_succeeded(&mut self) {
+        self.dropped = 0;
+        if let ConnectionState::Degraded { ref device, .. } = self.state {
+            self.state = ConnectionState::Attached {
+                device: device.clone(),
+            };
+        }
+    }
+
+    /// Moves to `Degraded`, or to `Disconnected` after `MAX_FAILED_READS` consecutive failures,
+    /// in which case true is returned and the source must be disconnected. A lost device is
+    /// attached again right away, but a device dropped again before any read succeeded is
+    /// retried with the backoff.
+    pub fn read_failed(&mut self, error: ConnectionError, now: Instant) -> bool {
+        let (device, failed_reads) = match self.state {
+            ConnectionState::Attached { ref device } => (device.clone(), 1),
+            ConnectionState::Degraded {
+                ref device,
+                failed_reads,
+                ..
+            } => (device.clone(), failed_reads + 1),
+            _ => return false,
+        };
+        if failed_reads >= MAX_FAILED_READS {
+            self.dropped += 1;
+            let delay = match self.dropped {
+                1 => Duration::ZERO,
+                dropped => retry_delay(dropped - 1),
+            };
+            self.state = ConnectionState::Disconnected {
+                reason: Some(error),
+                attempts: 0,
+                retry_at: now + delay,
+            };
+            return true;
+        }
+        self.state = ConnectionState::Degraded {
+            device,
+            failed_reads,
+            reason: error,
+        };
+        false
+    }
+}
+
+/// Delay after `attempts` failed attempts in a row.
+fn retry_delay(attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    (INITIAL_RETRY_DELAY * 2u32.pow(doublings)).min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_and_degrades() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut connection = Connection::new(start);
+
+        assert!(connection.start_attempt(at(0)));
+        assert_eq!(
+            connection.state(),
+            &ConnectionState::Connecting { attempt: 1 }
+        );
+        assert!(!connection.start_attempt(at(0)));
+        connection.attempt_failed(ConnectionError::NoDevice, at(0));
+        assert!(!connection.start_attempt(at(249)));
+        assert!(connection.start_attempt(at(250)));
+        connection.attempt_failed(ConnectionError::NoDevice, at(250));
+        assert!(!connection.start_attempt(at(749)));
+        assert!(connection.start_attempt(at(750)));
+        assert_eq!(
+            connection.state(),
+            &ConnectionState::Connecting { attempt: 3 }
+        );
+        assert_eq!(retry_delay(3), Duration::from_secs(1));
+        assert_eq!(retry_delay(100), MAX_RETRY_DELAY);
+
+        connection.attempt_succeeded(String::from("sd2snes"));
+        let failure = || ConnectionError::ReadFailed(String::from("timeout"));
+        assert!(!connection.read_failed(failure(), at(800)));
+        assert!(matches!(
+            connection.state(),
+            ConnectionState::Degraded {
+                failed_reads: 1,
+                ..
+            }
+        ));
+        connection.read_succeeded();
+        assert_eq!(
+            connection.state(),
+            &ConnectionState::Attached {
+                device: String::from("sd2snes")
+            }
+        );
+
+        for _ in 1..MAX_FAILED_READS {
+            assert!(!connection.read_failed(failure(), at(900)));
+        }
+        assert!(connection.read_failed(failure(), at(900)));
+        // Reconnects right away after losing the device
+        assert!(connection.start_attempt(at(900)));
+        assert_eq!(
+            connection.state(),
+            &ConnectionState::Connecting { attempt: 1 }
+        );
+    }
+
+    #[test]
+    fn backs_off_when_reads_fail_after_every_attach() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut connection = Connection::new(start);
+        let failure = || ConnectionError::ReadFailed(String::from("unsupported address"));
+        let attach_and_fail = |connection: &mut Connection, millis: u64| {
+            assert!(
+                connection.start_attempt(at(millis)),
+                "no attempt at {millis}ms"
+            );
+            connection.attempt_succeeded(String::from("retroarch"));
+            (0..MAX_FAILED_READS).any(|_| connection.read_failed(failure(), at(millis)))
+        };
+
+        // The first drop reconnects right away, the next ones wait longer every time
+        assert!(attach_and_fail(&mut connection, 0));
+        assert!(attach_and_fail(&mut connection, 0));
+        assert!(!connection.start_attempt(at(249)));
+        assert!(attach_and_fail(&mut connection, 250));
+        assert!(!connection.start_attempt(at(749)));
+        assert!(attach_and_fail(&mut connection, 750));
+        assert!(!connection.start_attempt(at(1749)));
+
+        // A successful read ends the streak
+        assert!(connection.start_attempt(at(1750)));
+        connection.attempt_succeeded(String::from("retroarch"));
+        connection.read_succeeded();
+        for _ in 0..MAX_FAILED_READS {
+            connection.read_failed(failure(), at(2000));
+        }
+        assert!(connection.start_attempt(at(2000)));
+    }
+}
@@ -0,0 +1,63 @@
+//! In-memory `MemoryReader` shared by the tests reading layouts and addresses.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::controller::controller_addresses::MAX_ADDRESS;
+use crate::input_source::MemoryReader;
+
+/// Longest range a usb2snes device answers in a single read.
+const MAX_READ_LENGTH: usize = 0xFF;
+
+/// Sparse memory map standing in for a usb2snes device, reading 0 at the addresses missing.
+/// Every request is logged.
+#[derive(Debug, Default)]
+pub struct FakeMemory {
+    pub bytes: HashMap<u32, u8>,
+    /// Ranges of every request, in order.
+    pub requests: Vec<Vec<(u32, usize)>>,
+    /// Rejects the ranges a usb2snes device would: longer than 255 bytes or outside of the
+    /// address space.
+    pub strict: bool,
+}
+
+impl FakeMemory {
+    pub fn new<const N: usize>(bytes: [(u32, u8); N]) -> Self {
+        Self {
+            bytes: HashMap::from(bytes),
+            ..Self::default()
+        }
+    }
+
+    pub fn strict(self) -> Self {
+        Self {
+            strict: true,
+            ..self
+        }
+    }
+
+    fn read_range(&self, address: u32, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let end = address as usize + length;
+        if self.strict && (length > MAX_READ_LENGTH || end > MAX_ADDRESS as usize + 1) {
+            return Err(format!("invalid range {address:06X}+{length:X}").into());
+        }
+        Ok((address..address + length as u32)
+            .map(|a| self.bytes.get(&a).copied().unwrap_or_default())
+            .collect())
+    }
+}
+
+impl MemoryReader for FakeMemory {
+    fn read_memory(&mut self, address: u32, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.read_many(&[(address, length)])
+            .map(|mut r| r.remove(0))
+    }
+
+    fn read_many(&mut self, ranges: &[(u32, usize)]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        self.requests.push(ranges.to_vec());
+        ranges
+            .iter()
+            .map(|&(address, length)| self.read_range(address, length))
+            .collect()
+    }
+}
@@ -0,0 +1,268 @@
+//! Local websocket server speaking the usb2snes protocol, backed by a fake memory map, to test
+//! the usb2snes source without QUsb2Snes or SNI running.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rusb2snes::USB2SnesEndpoint;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+/// Appended to the `Sec-WebSocket-Key` of the client before hashing, see RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// What the server knows and what it was asked. Tests can change it at any time.
+#[derive(Debug, Default)]
+pub struct MockState {
+    /// Answer to `DeviceList`.
+    pub devices: Vec<String>,
+    /// Answer to `Info`: firmware version, device type and ROM.
+    pub info: [String; 3],
+    /// Bytes answered to `GetAddress`, 0 for the addresses missing.
+    pub memory: HashMap<u32, u8>,
+    /// Name given by the last client.
+    pub name: Option<String>,
+    /// Device the last client attached to.
+    pub attached: Option<String>,
+    /// Opcode of every request, in order.
+    pub requests: Vec<String>,
+    /// Closes the connection on `GetAddress`, as a device failing its reads.
+    pub fail_reads: bool,
+    /// Bumped to close the connections opened before.
+    generation: u32,
+}
+
+impl MockState {
+    pub fn write(&mut self, address: u32, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.memory.insert(address + offset as u32, *byte);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Request {
+    opcode: String,
+    #[serde(default)]
+    operands: Vec<String>,
+}
+
+/// A usb2snes websocket listening on a free local port until the test ends.
+pub struct MockUsb2Snes {
+    port: u16,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockUsb2Snes {
+    pub fn start(devices: &[&str]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(MockState {
+            devices: devices.iter().map(|d| d.to_string()).collect(),
+            info: [
+                String::from("1.11.0"),
+                String::from("SD2SNES"),
+                String::from("/games/smw.sfc"),
+            ],
+            ..MockState::default()
+        }));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&shared);
+                thread::spawn(move || {
+                    // The client hanging up ends the connection as well as an error
+                    let _ = serve(stream, &state);
+                });
+            }
+        });
+        Self { port, state }
+    }
+
+    pub fn endpoint(&self) -> USB2SnesEndpoint {
+        USB2SnesEndpoint {
+            address: String::from("127.0.0.1"),
+            port: self.port,
+        }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Closes every open connection on its next request, as when the device is unplugged.
+    pub fn drop_connections(&self) {
+        self.state().generation += 1;
+    }
+}
+
+/// Answers the requests of a client until it disconnects or the connection is dropped.
+fn serve(mut stream: TcpStream, state: &Mutex<MockState>) -> Result<(), Box<dyn Error>> {
+    handshake(&mut stream)?;
+    let generation = state.lock().unwrap().generation;
+    let mut attached = false;
+    loop {
+        let (opcode, payload) = read_frame(&mut stream)?;
+        match opcode {
+            OPCODE_TEXT => {}
+            OPCODE_PING => {
+                write_frame(&mut stream, OPCODE_PONG, &payload)?;
+                continue;
+            }
+            _ => break,
+        }
+        let request: Request = serde_json::from_slice(&payload)?;
+        let mut state = state.lock().unwrap();
+        if state.generation != generation {
+            break;
+        }
+        state.requests.push(request.opcode.clone());
+        match (request.opcode.as_str(), attached) {
+            ("DeviceList", _) => {
+                let results = serde_json::json!({ "Results": state.devices });
+                write_frame(&mut stream, OPCODE_TEXT, results.to_string().as_bytes())?;
+            }
+            ("Name", _) => state.name = request.operands.first().cloned(),
+            ("Attach", _) => {
+                let device = request.operands.first().cloned().unwrap_or_default();
+                // QUsb2Snes closes the connection when the device does not exist
+                if !state.devices.contains(&device) {
+                    break;
+                }
+                state.attached = Some(device);
+                attached = true;
+            }
+            ("Info", true) => {
+                let results = serde_json::json!({ "Results": [
+                    state.info[0], state.info[1], state.info[2], "NO_CONTROL_CMD"
+                ] });
+                write_frame(&mut stream, OPCODE_TEXT, results.to_string().as_bytes())?;
+            }
+            ("GetAddress", true) if state.fail_reads => break,
+            ("GetAddress", true) => {
+                let mut bytes = Vec::new();
+                for range in request.operands.chunks(2) {
+                    let [address, length] = range else {
+                        return Err("odd GetAddress operands".into());
+                    };
+                    let address = u32::from_str_radix(address, 16)?;
+                    let length = u32::from_str_radix(length, 16)?;
+                    bytes.extend(
+                        (address..address + length)
+                            .map(|a| state.memory.get(&a).copied().unwrap_or_default()),
+                    );
+                }
+                write_frame(&mut stream, OPCODE_BINARY, &bytes)?;
+            }
+            // Unknown requests, or requests needing a device before attaching
+            _ => break,
+        }
+    }
+    let _ = write_frame(&mut stream, OPCODE_CLOSE, &[]);
+    stream.shutdown(Shutdown::Both)?;
+    Ok(())
+}
+
+/// Reads the HTTP upgrade request and accepts it.
+fn handshake(stream: &mut TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(&*stream);
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err("connection closed during the handshake".into());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key = key.ok_or("missing Sec-WebSocket-Key")?;
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    Ok(())
+}
+
+fn accept_key(key: &str) -> String {
+    let digest = sha1_smol::Sha1::from(format!("{key}{WEBSOCKET_GUID}")).digest();
+    STANDARD.encode(digest.bytes())
+}
+
+/// Reads a frame of the client, unmasking its payload.
+fn read_frame(stream: &mut TcpStream) -> Result<(u8, Vec<u8>), Box<dyn Error>> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut length = [0; 2];
+            stream.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as usize
+        }
+        127 => {
+            let mut length = [0; 8];
+            stream.read_exact(&mut length)?;
+            u64::from_be_bytes(length) as usize
+        }
+        length => length as usize,
+    };
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((header[0] & 0x0F, payload))
+}
+
+/// Writes an unmasked, unfragmented frame, as servers do.
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_websocket_handshake() {
+        // Example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::controller::{
+    controller_impl::ControllerLayout, frame_counter::FrameCount, port::PortState,
+    rom_header::RomHeader,
+};
+use crate::input_source::{
+    connection::{Connection, ConnectionError, ConnectionState},
+    DeviceInfo, InputSource, SourceStatus,
+};
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct PollingConfig {
+    /// Polls per second.
+    pub rate: f64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self { rate: 60.0 }
+    }
+}
+
+/// The state of every port at the time of a poll.
+#[derive(Debug, Clone)]
+pub struct PolledSample {
+    pub sequence: u32,
+    pub polled_at: Instant,
+    /// Name of the layout the ports were read with. Samples queued before a layout switch
+    /// still hold the ports of the previous layout.
+    pub layout: String,
+    pub frame: Option<FrameCount>,
+    pub ports: Vec<PortState>,
+    /// Round trip of every memory read of the poll.
+    pub read_times: Vec<Duration>,
+}
+
+/// What happened on the polling thread, in order.
+#[derive(Debug)]
+pub enum PollEvent {
+    Sample(PolledSample),
+    /// The source attached to a device. The header of the running ROM is read right away,
+    /// when the source gives access to the console memory.
+    Attached {
+        description: String,
+        /// Every device the source could attach to.
+        devices: Vec<String>,
+        info: Option<DeviceInfo>,
+        rom_header: Result<Option<RomHeader>, String>,
+    },
+    /// The connection moved to a new state.
+    ConnectionChanged(ConnectionState),
+    /// Whether the attached device can read the current layout, checked when attaching and
+    /// when the layout changes. Layouts it can't read are not polled until the next check.
+    LayoutChecked(Result<(), String>),
+}
+
+/// Polls an `InputSource` on a dedicated thread at a fixed rate, so slow devices don't stall
+/// rendering. Samples are timestamped when they are read and queued until the viewer drains
+/// them with `events`.
+pub struct Poller {
+    source: Arc<Mutex<Box<dyn InputSource>>>,
+    layouts: Sender<(String, ControllerLayout)>,
+    device_requests: Sender<String>,
+    events: Receiver<PollEvent>,
+    connection: Arc<Mutex<ConnectionState>>,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Poller {
+    pub fn start(
+        source: Box<dyn InputSource>,
+        layout_name: &str,
+        layout: ControllerLayout,
+        config: &PollingConfig,
+    ) -> Result<Self, String> {
+        if !(config.rate.is_finite() && config.rate > 0.0) {
+            return Err(format!("invalid polling rate {}", config.rate));
+        }
+        let period = Duration::from_secs_f64(1.0 / config.rate);
+        let layout_name = layout_name.to_string();
+        let source = Arc::new(Mutex::new(source));
+        let (layouts, layout_updates) = mpsc::channel();
+        let (device_requests, device_updates) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let connection = Connection::new(Instant::now());
+        let state = Arc::new(Mutex::new(connection.state().clone()));
+
+        let worker = {
+            let source = Arc::clone(&source);
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name(String::from("input poller"))
+                .spawn(move || {
+                    let mut worker = Worker {
+                        source,
+                        layout_name,
+                        layout,
+                        layout_updates,
+                        device_updates,
+                        events: event_sender,
+                        connection,
+                        state,
+                        sequence: 0,
+                        layout_supported: true,
+                    };
+                    let mut next_tick = Instant::now();
+                    while !stop.load(Ordering::Relaxed) && worker.tick() {
+                        next_tick += period;
+                        let now = Instant::now();
+                        match next_tick.checked_duration_since(now) {
+                            Some(wait) => thread::sleep(wait),
+                            // Too slow to keep up, don't try to catch up
+                            None => next_tick = now,
+                        }
+                    }
+                })
+                .map_err(|e| format!("could not start the polling thread: {e}"))?
+        };
+
+        Ok(Self {
+            source,
+            layouts,
+            device_requests,
+            events,
+            connection: state,
+            stop,
+            worker: Some(worker),
+        })
+    }
+
+    /// Polls with `layout` from now on, tagging the samples with its `name`.
+    pub fn set_layout(&self, name: &str, layout: ControllerLayout) {
+        // The worker only stops when the poller is dropped
+        let _ = self.layouts.send((name.to_string(), layout));
+    }
+
+    /// Switches to `device`, attaching to it right away.
+    pub fn select_device(&self, device: String) {
+        let _ = self.device_requests.send(device);
+    }
+
+    /// Events that happened since the previous call.
+    pub fn events(&self) -> impl Iterator<Item = PollEvent> + '_ {
+        self.events.try_iter()
+    }
+
+    /// Current state of the connection to the source.
+    pub fn connection(&self) -> ConnectionState {
+        lock(&self.connection).clone()
+    }
+
+    /// Locks the source, waiting for the poll in progress if any.
+    pub fn source(&self) -> MutexGuard<'_, Box<dyn InputSource>> {
+        lock(&self.source)
+    }
+}
+
+/// Locks `mutex`, ignoring the poisoning left by a panic of the other thread.
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Worker {
+    source: Arc<Mutex<Box<dyn InputSource>>>,
+    layout_name: String,
+    layout: ControllerLayout,
+    layout_updates: Receiver<(String, ControllerLayout)>,
+    device_updates: Receiver<String>,
+    events: Sender<PollEvent>,
+    connection: Connection,
+    /// Copy of the connection state shared with the `Poller`.
+    state: Arc<Mutex<ConnectionState>>,
+    sequence: u32,
+    /// Result of the last `InputSource::check_layout`.
+    layout_supported: bool,
+}
+
+impl Worker {
+    /// Polls the source once, or connects it when an attempt is due. Returns false when the
+    /// poller is gone.
+    fn tick(&mut self) -> bool {
+        let mut check_layout = false;
+        if let Some((name, layout)) = self.layout_updates.try_iter().last() {
+            self.layout_name = name;
+            self.layout = layout;
+            check_layout = true;
+        }
+        let source = Arc::clone(&self.source);
+        let mut source = lock(&source);
+        let now = Instant::now();
+        if let Some(device) = self.device_updates.try_iter().last() {
+            source.select_device(&device);
+            self.connection.reconnect(now);
+        }
+        let previous = self.connection.state().clone();
+        let event = match previous {
+            // A configuration error, not a read failure to reconnect for
+            ConnectionState::Attached { .. } | ConnectionState::Degraded { .. }
+                if !self.layout_supported =>
+            {
+                None
+            }
+            ConnectionState::Attached { .. } | ConnectionState::Degraded { .. } => {
+                match source.poll(&self.layout) {
+                    Ok(state) => {
+                        self.connection.read_succeeded();
+                        self.sequence = self.sequence.wrapping_add(1);
+                        Some(PollEvent::Sample(PolledSample {
+                            sequence: self.sequence,
+                            polled_at: now,
+                            layout: self.layout_name.clone(),
+                            frame: state.frame,
+                            ports: state.ports,
+                            read_times: source.take_read_times(),
+                        }))
+                    }
+                    Err(e) => {
+                        let error = ConnectionError::ReadFailed(e.to_string());
+                        if self.connection.read_failed(error, now) {
+                            source.disconnect();
+                        }
+                        None
+                    }
+                }
+            }
+            ConnectionState::Disconnected { .. } | ConnectionState::Connecting { .. } => {
+                match self.connection.start_attempt(now) {
+                    true => {
+                        check_layout = true;
+                        self.connect(source.as_mut(), now)
+                    }
+                    false => None,
+                }
+            }
+        };
+
+        let state = self.connection.state();
+        let mut events = Vec::new();
+        if *state != previous {
+            *lock(&self.state) = state.clone();
+            events.push(PollEvent::ConnectionChanged(state.clone()));
+        }
+        events.extend(event);
+        let attached = matches!(
+            state,
+            ConnectionState::Attached { .. } | ConnectionState::Degraded { .. }
+        );
+        if check_layout && attached {
+            let checked = source.check_layout(&self.layout);
+            self.layout_supported = checked.is_ok();
+            events.push(PollEvent::LayoutChecked(checked));
+        }
+        events
+            .into_iter()
+            .all(|event| self.events.send(event).is_ok())
+    }
+
+    /// Connects the source and reads the header of the running ROM once attached.
+    fn connect(&mut self, source: &mut dyn InputSource, now: Instant) -> Option<PollEvent> {
+        if let Err(e) = source.connect() {
+            self.connection.attempt_failed(e, now);
+            return None;
+        }
+        let SourceStatus::Attached(device) = source.status() else {
+            self.connection
+                .attempt_failed(ConnectionError::NoDevice, now);
+            return None;
+        };
+        self.connection.attempt_succeeded(device);
+        Some(PollEvent::Attached {
+            description: source.describe(),
+            devices: source.devices(),
+            info: source.device_info(),
+            rom_header: match source.memory() {
+                Some(memory) => RomHeader::read(memory).map_err(|e| e.to_string()),
+                None => Ok(None),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::button_state::ButtonState;
+    use crate::input_source::replay::ReplaySource;
+    use crate::recording::input_log::Sample;
+
+    #[test]
+    fn polls_on_a_background_thread() {
+        let sample = Sample {
+            sequence: 1,
+            elapsed: Duration::ZERO,
+            frame: None,
+            ports: vec![PortState::Joypad(ButtonState::from_le_bytes([0x80, 0]))],
+        };
+        let source = Box::new(ReplaySource::new("test", vec![sample]));
+        let layout: ControllerLayout =
+            serde_json::from_str(r#"{ "source": "joypad_register" }"#).unwrap();
+        let poller = Poller::start(
+            source,
+            "Any",
+            layout.clone(),
+            &PollingConfig { rate: 200.0 },
+        )
+        .unwrap();
+        let drain = |poller: &Poller| -> Vec<PolledSample> {
+            thread::sleep(Duration::from_millis(100));
+            poller
+                .events()
+                .filter_map(|event| match event {
+                    PollEvent::Sample(sample) => Some(sample),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let samples = drain(&poller);
+        poller.set_layout("Other", layout);
+        let switched = drain(&poller);
+        assert!(samples.len() > 2, "got {} samples", samples.len());
+        assert!(samples.windows(2).all(|w| w[0].sequence < w[1].sequence));
+        assert!(samples.windows(2).all(|w| w[0].polled_at < w[1].polled_at));
+        assert_eq!(
+            samples[0].ports,
+            vec![PortState::Joypad(ButtonState::from_le_bytes([0x80, 0]))]
+        );
+        assert!(samples.iter().all(|sample| sample.layout == "Any"));
+        // Samples are tagged with the layout they were read with
+        assert_eq!(switched.last().unwrap().layout, "Other");
+        assert!(poller.source().playback().is_some());
+        assert_eq!(
+            poller.connection(),
+            ConnectionState::Attached {
+                device: String::from("test")
+            }
+        );
+
+        assert!(Poller::start(
+            Box::new(ReplaySource::new("test", Vec::new())),
+            "Any",
+            serde_json::from_str(r#"{ "source": "joypad_register" }"#).unwrap(),
+            &PollingConfig { rate: 0.0 }
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+
+use crate::input_source::MemoryReader;
+
+/// Largest number of bytes fetched by a single range of a request.
+const MAX_RANGE_LENGTH: usize = 0xFF;
+/// Ranges closer than this are fetched as one, the bytes in between being cheaper to transfer
+/// than another range.
+const MERGE_GAP: u32 = 0x10;
+/// Most requests made for a single poll. Every pointer level or selector needs one more.
+const MAX_ROUNDS: usize = 8;
+
+/// Contiguous ranges covering every `(address, length)` read, sorted by address.
+pub fn plan(reads: &[(u32, usize)]) -> Vec<(u32, usize)> {
+    let mut reads: Vec<(u32, usize)> = reads.iter().copied().filter(|r| r.1 > 0).collect();
+    reads.sort_unstable();
+    let mut ranges: Vec<(u32, usize)> = Vec::new();
+    for (address, length) in reads {
+        let end = address + length as u32;
+        if let Some((start, range_length)) = ranges.last_mut() {
+            let range_end = *start + *range_length as u32;
+            let merged_length = (end.max(range_end) - *start) as usize;
+            if address <= range_end + MERGE_GAP && merged_length <= MAX_RANGE_LENGTH {
+                *range_length = merged_length;
+                continue;
+            }
+        }
+        ranges.push((address, length));
+    }
+    ranges
+}
+
+/// `MemoryReader` answering from the ranges fetched so far. Reads outside of them are noted
+/// and answered with zeros, to be fetched in the next round.
+struct BatchedReader {
+    fetched: Vec<(u32, Vec<u8>)>,
+    missing: Vec<(u32, usize)>,
+}
+
+impl BatchedReader {
+    fn cached(&self, address: u32, length: usize) -> Option<Vec<u8>> {
+        self.fetched.iter().find_map(|(start, bytes)| {
+            let offset = address.checked_sub(*start)? as usize;
+            bytes.get(offset..offset + length).map(<[u8]>::to_vec)
+        })
+    }
+}
+
+impl MemoryReader for BatchedReader {
+    fn read_memory(&mut self, address: u32, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self.cached(address, length) {
+            Some(bytes) => bytes,
+            None => {
+                self.missing.push((address, length));
+                vec![0; length]
+            }
+        })
+    }
+}
+
+/// Runs `read` with the fewest requests to `reader`: a first dry run collects every address
+/// `read` needs, which are fetched together, then `read` runs again on the fetched bytes.
+/// Addresses that depend on fetched values, like pointers, take another round.
+pub fn read_batched<R, T>(
+    reader: &mut R,
+    mut read: impl FnMut(&mut dyn MemoryReader) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>>
+where
+    R: MemoryReader + ?Sized,
+{
+    let mut batched = BatchedReader {
+        fetched: Vec::new(),
+        missing: Vec::new(),
+    };
+    for _ in 0..MAX_ROUNDS {
+        let result = read(&mut batched);
+        if batched.missing.is_empty() {
+            return result;
+        }
+        let ranges = plan(&batched.missing);
+        batched.missing.clear();
+        let fetched = reader.read_many(&ranges)?;
+        for (&(address, length), bytes) in ranges.iter().zip(fetched) {
+            if bytes.len() < length {
+                return Err(format!("short read at {address:06X}").into());
+            }
+            batched.fetched.push((address, bytes));
+        }
+    }
+    Err(format!("addresses still unresolved after {MAX_ROUNDS} requests").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_source::fake_memory::FakeMemory;
+
+    #[test]
+    fn merges_close_reads() {
+        let ranges = plan(&[
+            (0xF50DA4, 1),
+            (0xF50DA2, 1),
+            (0xF90718, 2),
+            (0xF50013, 1),
+            (0xF50DA2, 1),
+            (0xF50100, 0),
+        ]);
+        assert_eq!(ranges, [(0xF50013, 1), (0xF50DA2, 3), (0xF90718, 2)]);
+
+        // Ranges never grow past MAX_RANGE_LENGTH
+        let ranges = plan(&[(0xF50000, 0xF8), (0xF50100, 8)]);
+        assert_eq!(ranges, [(0xF50000, 0xF8), (0xF50100, 8)]);
+    }
+
+    #[test]
+    fn fetches_every_address_in_one_request_per_round() {
+        let mut memory = FakeMemory::new([(0xF50010, 0x00), (0xF50011, 0x20), (0xF52000, 0xAB)]);
+        let read = |reader: &mut dyn MemoryReader| {
+            let first = reader.read_memory(0xF50DA4, 1)?[0];
+            let second = reader.read_memory(0xF90718, 2)?[1];
+            Ok((first, second))
+        };
+        assert_eq!(read_batched(&mut memory, read).unwrap(), (0, 0));
+        assert_eq!(memory.requests, [vec![(0xF50DA4, 1), (0xF90718, 2)]]);
+
+        // A pointer needs a second round
+        memory.requests.clear();
+        let read = |reader: &mut dyn MemoryReader| {
+            let pointer = reader.read_memory(0xF50010, 2)?;
+            let address = 0xF50000 | u32::from_le_bytes([pointer[0], pointer[1], 0, 0]);
+            Ok(reader.read_memory(address, 1)?[0])
+        };
+        assert_eq!(read_batched(&mut memory, read).unwrap(), 0xAB);
+        assert_eq!(
+            memory.requests,
+            [vec![(0xF50000, 0x12)], vec![(0xF52000, 1)]]
+        );
+    }
+}
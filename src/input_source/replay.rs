@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::controller::controller_impl::{ControllerLayout, LayoutState};
+use crate::input_source::{connection::ConnectionError, InputSource, Playback, SourceStatus};
+use crate::movie::MovieFormat;
+use crate::recording::{input_log::Sample, Session};
+
+/// Playback speeds selectable with `Playback::change_speed`.
+const SPEEDS: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED_INDEX: usize = 2;
+
+/// `InputSource` replaying recorded samples at their original timing. The layout passed to
+/// `poll` is ignored, every recorded port is returned.
+pub struct ReplaySource {
+    name: String,
+    samples: Vec<Sample>,
+    position: Duration,
+    paused: bool,
+    speed_index: usize,
+    last_tick: Option<Instant>,
+}
+
+impl ReplaySource {
+    /// Creates a replay of `samples`, which must be sorted by `elapsed`.
+    pub fn new(name: &str, samples: Vec<Sample>) -> Self {
+        debug_assert!(samples.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+        Self {
+            name: name.to_string(),
+            samples,
+            position: Duration::ZERO,
+            paused: false,
+            speed_index: NORMAL_SPEED_INDEX,
+            last_tick: None,
+        }
+    }
+
+    /// Loads a replay from an input log file, or from an emulator movie in which case the
+    /// inputs of `player` (0 based) are replayed.
+    pub fn open(path: &Path, player: usize) -> Result<Self, Box<dyn Error>> {
+        let samples = match MovieFormat::from_path(path) {
+            Some(format) => {
+                let movie = format.load(path, player)?;
+                println!(
+                    "Replaying {} frames of player {} from {:?} movie",
+                    movie.frames.len(),
+                    player + 1,
+                    format
+                );
+                movie.to_samples()
+            }
+            None => {
+                let session = Session::load(path)?;
+                println!(
+                    "Replaying {} inputs recorded with layout {}",
+                    session.samples.len(),
+                    session.layouts.join(", ")
+                );
+                session.samples
+            }
+        };
+        if samples.is_empty() {
+            return Err(format!("{} contains no inputs", path.display()).into());
+        }
+
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        Ok(Self::new(&name, samples))
+    }
+
+    fn duration(&self) -> Duration {
+        self.samples.last().map(|s| s.elapsed).unwrap_or_default()
+    }
+
+    /// Index of the sample displayed at the current position.
+    fn current_index(&self) -> Option<usize> {
+        self.samples
+            .partition_point(|s| s.elapsed <= self.position)
+            .checked_sub(1)
+    }
+
+    /// Moves the playback position according to the time elapsed since the previous call.
+    fn advance(&mut self, now: Instant) {
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused {
+                let elapsed = now.saturating_duration_since(last_tick);
+                self.position += elapsed.mul_f64(SPEEDS[self.speed_index]);
+                if self.position >= self.duration() {
+                    self.position = self.duration();
+                    self.paused = true;
+                }
+            }
+        }
+        self.last_tick = Some(now);
+    }
+
+    fn state(&self) -> LayoutState {
+        self.current_index()
+            .map(|i| LayoutState {
+                frame: self.samples[i].frame,
+                ports: self.samples[i].ports.clone(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl InputSource for ReplaySource {
+    fn connect(&mut self) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    fn poll(&mut self, _layout: &ControllerLayout) -> Result<LayoutState, Box<dyn Error>> {
+        self.advance(Instant::now());
+        Ok(self.state())
+    }
+
+    fn status(&self) -> SourceStatus {
+        SourceStatus::Attached(self.name.clone())
+    }
+
+    fn describe(&self) -> String {
+        format!("replay of {}", self.name)
+    }
+
+    fn playback(&mut self) -> Option<&mut dyn Playback> {
+        Some(self)
+    }
+}
+
+impl Playback for ReplaySource {
+    fn toggle_pause(&mut self) {
+        if self.paused && self.position >= self.duration() {
+            self.position = Duration::ZERO;
+        }
+        self.paused = !self.paused;
+    }
+
+    fn seek(&mut self, offset: Duration, backward: bool) {
+        self.position = match backward {
+            true => self.position.saturating_sub(offset),
+            false => self.position.saturating_add(offset).min(self.duration()),
+        };
+    }
+
+    fn step(&mut self, backward: bool) {
+        self.paused = true;
+        if self.samples.is_empty() {
+            return;
+        }
+        let index = match (self.current_index(), backward) {
+            (None, _) => 0,
+            (Some(i), true) => i.saturating_sub(1),
+            (Some(i), false) => (i + 1).min(self.samples.len() - 1),
+        };
+        self.position = self.samples[index].elapsed;
+    }
+
+    fn change_speed(&mut self, slower: bool) {
+        self.speed_index = match slower {
+            true => self.speed_index.saturating_sub(1),
+            false => (self.speed_index + 1).min(SPEEDS.len() - 1),
+        };
+    }
+
+    fn state_label(&self) -> String {
+        let seconds = self.position.as_secs_f64();
+        let mut label = format!("{}:{:04.1}", (seconds / 60.0) as u64, seconds % 60.0);
+        if self.paused {
+            label.insert_str(0, "paused ");
+        }
+        if self.speed_index != NORMAL_SPEED_INDEX {
+            label.push_str(&format!(" x{}", SPEEDS[self.speed_index]));
+        }
+        label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{button_state::ButtonState, port::PortState};
+
+    fn sample(sequence: u32, millis: u64, low: u8) -> Sample {
+        Sample {
+            sequence,
+            elapsed: Duration::from_millis(millis),
+            frame: None,
+            ports: vec![PortState::Joypad(ButtonState::from_le_bytes([low, 0]))],
+        }
+    }
+
+    fn low_byte(replay: &ReplaySource) -> u8 {
+        replay.state().ports[0].joypad().unwrap().to_le_bytes()[0]
+    }
+
+    #[test]
+    fn replays_at_original_timing_and_speed() {
+        let mut replay = ReplaySource::new(
+            "test",
+            vec![
+                sample(1, 0, 0x10),
+                sample(2, 100, 0x20),
+                sample(3, 200, 0x40),
+            ],
+        );
+        let start = Instant::now();
+        replay.advance(start);
+        assert_eq!(low_byte(&replay), 0x10);
+
+        replay.advance(start + Duration::from_millis(150));
+        assert_eq!(low_byte(&replay), 0x20);
+
+        replay.change_speed(true);
+        replay.advance(start + Duration::from_millis(250));
+        assert_eq!(replay.position, Duration::from_millis(200));
+        assert_eq!(low_byte(&replay), 0x40);
+        assert!(replay.paused, "playback pauses at the end of the recording");
+    }
+
+    #[test]
+    fn step_and_seek() {
+        let mut replay = ReplaySource::new(
+            "test",
+            vec![
+                sample(1, 0, 0x10),
+                sample(2, 100, 0x20),
+                sample(3, 5000, 0x40),
+            ],
+        );
+        replay.step(false);
+        assert!(replay.paused);
+        assert_eq!(low_byte(&replay), 0x20);
+        replay.step(true);
+        assert_eq!(low_byte(&replay), 0x10);
+
+        replay.seek(Duration::from_secs(10), false);
+        assert_eq!(replay.position, Duration::from_millis(5000));
+        replay.seek(Duration::from_secs(1), true);
+        assert_eq!(low_byte(&replay), 0x20);
+        assert_eq!(replay.state_label(), "paused 0:04.0");
+
+        // An empty recording has nothing to step to
+        let mut empty = ReplaySource::new("empty", Vec::new());
+        empty.step(false);
+        empty.step(true);
+        assert_eq!(empty.state(), LayoutState::default());
+    }
+}
@@ -0,0 +1,560 @@
+use rusb2snes::{SyncClient, USB2SnesEndpoint};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::controller::controller_impl::{ControllerLayout, LayoutState};
+use crate::input_source::{
+    connection::ConnectionError, DeviceInfo, InputSource, MemoryReader, SourceStatus,
+};
+use crate::input_viewer::APP_NAME;
+
+impl MemoryReader for SyncClient {
+    fn read_memory(&mut self, address: u32, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.get_address(address, length)
+    }
+
+    fn read_many(&mut self, ranges: &[(u32, usize)]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        self.get_addresses(ranges)
+    }
+}
+
+/// Measures the round trip of every read going through it.
+struct TimedReader<'a> {
+    client: &'a mut SyncClient,
+    read_times: &'a mut Vec<Duration>,
+}
+
+impl MemoryReader for TimedReader<'_> {
+    fn read_memory(&mut self, address: u32, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let start = Instant::now();
+        let bytes = self.client.get_address(address, length);
+        self.read_times.push(start.elapsed());
+        bytes
+    }
+
+    fn read_many(&mut self, ranges: &[(u32, usize)]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let start = Instant::now();
+        let bytes = self.client.get_addresses(ranges);
+        self.read_times.push(start.elapsed());
+        bytes
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct DeviceConfig {
+    /// Part of the name of the device to attach to, case insensitive, e.g. `sd2snes` or
+    /// `snes9x`. The last device used, or the first one listed, is attached otherwise.
+    pub name: Option<String>,
+}
+
+/// File remembering the last attached device, next to the default settings file.
+fn last_device_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_local_dir()?
+            .join("snes-input-display")
+            .join("last_device"),
+    )
+}
+
+/// Picks the device to attach to among `devices`, by order of preference: the device selected
+/// at runtime, the first one matching the configured name, the last device used, the first
+/// device listed.
+fn choose_device<'a>(
+    devices: &'a [String],
+    selected: Option<&str>,
+    name: Option<&str>,
+    last_device: Option<&str>,
+) -> Result<&'a String, ConnectionError> {
+    if devices.is_empty() {
+        return Err(ConnectionError::NoDevice);
+    }
+    let find = |device: Option<&str>| devices.iter().find(|d| Some(d.as_str()) == device);
+    if let Some(device) = find(selected) {
+        return Ok(device);
+    }
+    if let Some(name) = name {
+        let pattern = name.to_lowercase();
+        return devices
+            .iter()
+            .find(|d| d.to_lowercase().contains(&pattern))
+            .ok_or_else(|| ConnectionError::NoMatchingDevice(name.to_string()));
+    }
+    Ok(find(last_device).unwrap_or(&devices[0]))
+}
+
+/// `InputSource` reading the controller state from the console memory through a usb2snes
+/// compatible websocket (QUsb2Snes, SNI).
+pub struct Usb2SnesSource {
+    endpoint: USB2SnesEndpoint,
+    config: DeviceConfig,
+    client: Option<SyncClient>,
+    device: Option<String>,
+    /// Devices listed by the last connection.
+    devices: Vec<String>,
+    /// Device chosen at runtime, preferred over the configured one.
+    selected: Option<String>,
+    last_device: Option<String>,
+    last_device_path: Option<PathBuf>,
+    /// Answer of the device to the `Info` command, when attached.
+    info: Option<DeviceInfo>,
+    read_times: Vec<Duration>,
+}
+
+impl Usb2SnesSource {
+    pub fn new(endpoint: USB2SnesEndpoint, config: DeviceConfig) -> Self {
+        let last_device_path = last_device_path();
+        let last_device = last_device_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|device| device.trim().to_string());
+        Self {
+            endpoint,
+            config,
+            client: None,
+            device: None,
+            devices: Vec::new(),
+            selected: None,
+            last_device,
+            last_device_path,
+            info: None,
+            read_times: Vec::new(),
+        }
+    }
+
+    fn remember_device(&mut self, device: &str) {
+        if self.last_device.as_deref() == Some(device) {
+            return;
+        }
+        self.last_device = Some(device.to_string());
+        let saved = match self.last_device_path {
+            Some(ref path) => path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(path, device))
+                .map_err(|e| e.to_string()),
+            None => Err(String::from("no configuration directory")),
+        };
+        if let Err(e) = saved {
+            println!("Could not remember the device: {e}");
+        }
+    }
+}
+
+impl InputSource for Usb2SnesSource {
+    fn connect(&mut self) -> Result<(), ConnectionError> {
+        self.disconnect();
+        let unavailable = |e: Box<dyn Error>| ConnectionError::WebsocketUnavailable(e.to_string());
+        let mut client = SyncClient::connect(&self.endpoint).map_err(unavailable)?;
+        client
+            .set_name(String::from(APP_NAME))
+            .map_err(unavailable)?;
+        self.devices = client
+            .list_device()
+            .map_err(|_| ConnectionError::NoDevice)?;
+        let device = choose_device(
+            &self.devices,
+            self.selected.as_deref(),
+            self.config.name.as_deref(),
+            self.last_device.as_deref(),
+        )?
+        .clone();
+        client
+            .attach(&device)
+            .map_err(|e| ConnectionError::AttachFailed(e.to_string()))?;
+        println!("Attached to {}", device);
+        self.info = match client.info() {
+            Ok(info) => Some(DeviceInfo {
+                firmware: info.version,
+                kind: info.dev_type,
+                rom: info.game,
+            }),
+            Err(e) => {
+                println!("Could not read the device info: {e}");
+                None
+            }
+        };
+        self.remember_device(&device);
+        self.device = Some(device);
+        self.client = Some(client);
+        Ok(())
+    }
+
+    fn devices(&self) -> Vec<String> {
+        self.devices.clone()
+    }
+
+    fn select_device(&mut self, device: &str) {
+        self.selected = Some(device.to_string());
+        self.disconnect();
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.device = None;
+        self.info = None;
+    }
+
+    fn check_layout(&self, layout: &ControllerLayout) -> Result<(), String> {
+        match self.info {
+            Some(ref info) if !info.exposes_cpu_registers() && layout.reads_joypad_registers() => {
+                Err(format!(
+                    "{} does not expose the joypad registers, they need a SD2SNES/FxPak Pro",
+                    info.kind
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn poll(&mut self, layout: &ControllerLayout) -> Result<LayoutState, Box<dyn Error>> {
+        let client = self
+            .client
+            .as_mut()
+            .ok_or("Not connected to usb2snes websocket")?;
+        let mut reader = TimedReader {
+            client,
+            read_times: &mut self.read_times,
+        };
+        layout.read(&mut reader)
+    }
+
+    fn memory(&mut self) -> Option<&mut dyn MemoryReader> {
+        self.device.as_ref()?;
+        self.client.as_mut().map(|c| c as &mut dyn MemoryReader)
+    }
+
+    fn take_read_times(&mut self) -> Vec<Duration> {
+        std::mem::take(&mut self.read_times)
+    }
+
+    fn status(&self) -> SourceStatus {
+        match (&self.client, &self.device) {
+            (None, _) => SourceStatus::Disconnected,
+            (Some(_), None) => SourceStatus::Connected,
+            (Some(_), Some(d)) => SourceStatus::Attached(d.clone()),
+        }
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        self.info.clone()
+    }
+
+    fn describe(&self) -> String {
+        match self.device {
+            Some(ref d) => format!("usb2snes device {}", d),
+            None => String::from("usb2snes"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{
+        button_state::ButtonState, controller_addresses::JOYPAD_REGISTERS, port::PortState,
+    };
+    use crate::input_source::{
+        connection::ConnectionState,
+        mock_usb2snes::MockUsb2Snes,
+        poller::{PollEvent, Poller, PollingConfig},
+    };
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A source connecting to `endpoint`, remembering its device in a temporary file.
+    fn source(endpoint: USB2SnesEndpoint, name: Option<&str>) -> Usb2SnesSource {
+        let mut source = Usb2SnesSource::new(
+            endpoint.clone(),
+            DeviceConfig {
+                name: name.map(String::from),
+            },
+        );
+        source.last_device = None;
+        source.last_device_path = Some(std::env::temp_dir().join(format!(
+            "snes-input-display-last-device-{}-{}",
+            std::process::id(),
+            endpoint.port
+        )));
+        source
+    }
+
+    fn layout() -> ControllerLayout {
+        serde_json::from_str(
+            r#"{
+                "address_low": "F50DA4",
+                "address_high": "F50DA2",
+                "frame_counter": { "address": "F50013" }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    /// Connection states the poller goes through until `done` returns true, or 2 seconds.
+    fn connection_changes(
+        poller: &Poller,
+        done: impl Fn(&[ConnectionState]) -> bool,
+    ) -> Vec<ConnectionState> {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut states = Vec::new();
+        while !done(&states) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            states.extend(poller.events().filter_map(|event| match event {
+                PollEvent::ConnectionChanged(state) => Some(state),
+                _ => None,
+            }));
+        }
+        states
+    }
+
+    fn is_attached(state: &ConnectionState) -> bool {
+        matches!(state, ConnectionState::Attached { .. })
+    }
+
+    #[test]
+    fn attaches_and_reads_a_layout() {
+        let server = MockUsb2Snes::start(&["SD2SNES COM3", "Snes9x"]);
+        server.state().write(0xF50013, &[0x2A]);
+        server.state().write(0xF50DA2, &[0x80, 0x00, 0x40]);
+        let mut source = source(server.endpoint(), Some("snes9x"));
+        // The configuration directory is created if needed
+        let directory = std::env::temp_dir().join(format!(
+            "snes-input-display-config-{}-{}",
+            std::process::id(),
+            server.endpoint().port
+        ));
+        source.last_device_path = Some(directory.join("last_device"));
+
+        source.connect().unwrap();
+        assert_eq!(
+            source.status(),
+            SourceStatus::Attached(String::from("Snes9x"))
+        );
+        assert_eq!(source.devices(), ["SD2SNES COM3", "Snes9x"]);
+        assert_eq!(server.state().name.as_deref(), Some(APP_NAME));
+        assert_eq!(server.state().attached.as_deref(), Some("Snes9x"));
+        assert_eq!(
+            source.device_info(),
+            Some(DeviceInfo {
+                firmware: String::from("1.11.0"),
+                kind: String::from("SD2SNES"),
+                rom: String::from("/games/smw.sfc"),
+            })
+        );
+        let last_device = source.last_device_path.clone().unwrap();
+        assert_eq!(fs::read_to_string(last_device).unwrap(), "Snes9x");
+        fs::remove_dir_all(directory).unwrap();
+
+        let state = source.poll(&layout()).unwrap();
+        assert_eq!(
+            state.ports,
+            [PortState::Joypad(ButtonState::from_le_bytes([0x40, 0x80]))]
+        );
+        assert_eq!(state.frame.unwrap().value, 0x2A);
+        // Every address in a single request
+        assert_eq!(source.take_read_times().len(), 1);
+        assert_eq!(
+            server.state().requests,
+            ["Name", "DeviceList", "Attach", "Info", "GetAddress"]
+        );
+    }
+
+    #[test]
+    fn reads_joypad_registers_only_on_devices_exposing_them() {
+        let server = MockUsb2Snes::start(&["SD2SNES COM3"]);
+        server.state().write(JOYPAD_REGISTERS, &[0x80, 0x00]);
+        let mut source = source(server.endpoint(), None);
+        let registers: ControllerLayout =
+            serde_json::from_str(r#"{ "source": "joypad_register" }"#).unwrap();
+
+        source.connect().unwrap();
+        assert_eq!(source.check_layout(&registers), Ok(()));
+        assert_eq!(
+            source.poll(&registers).unwrap().ports,
+            [PortState::Joypad(ButtonState::from_le_bytes([0x80, 0x00]))]
+        );
+
+        for kind in ["fxpakpro", "RETROARCH", "SNES9X", "emunw"] {
+            server.state().info[1] = String::from(kind);
+            source.connect().unwrap();
+            let checked = source.check_layout(&registers);
+            match kind {
+                "fxpakpro" => assert_eq!(checked, Ok(())),
+                _ => assert!(checked.unwrap_err().starts_with(kind)),
+            }
+            // Layouts reading the memory work everywhere
+            assert_eq!(source.check_layout(&layout()), Ok(()));
+        }
+        fs::remove_file(source.last_device_path.unwrap()).unwrap();
+    }
+
+    #[test]
+    fn does_not_poll_layouts_the_device_cannot_read() {
+        let server = MockUsb2Snes::start(&["RetroArch"]);
+        server.state().info[1] = String::from("RETROARCH");
+        let source = Box::new(source(server.endpoint(), None));
+        let last_device = source.last_device_path.clone().unwrap();
+        let registers: ControllerLayout =
+            serde_json::from_str(r#"{ "source": "joypad_register" }"#).unwrap();
+        let poller =
+            Poller::start(source, "Default", registers, &PollingConfig { rate: 200.0 }).unwrap();
+
+        thread::sleep(Duration::from_millis(300));
+        let events: Vec<_> = poller.events().collect();
+        let refused = events.iter().any(|event| {
+            matches!(event, PollEvent::LayoutChecked(Err(reason)) if reason.starts_with("RETROARCH"))
+        });
+        assert!(refused, "{events:?}");
+        // Neither read failures nor reconnections
+        assert!(events.iter().all(|event| !matches!(
+            event,
+            PollEvent::Sample(_)
+                | PollEvent::ConnectionChanged(
+                    ConnectionState::Degraded { .. } | ConnectionState::Disconnected { .. }
+                )
+        )));
+        // Only the ROM header was read, when attaching
+        let reads = |server: &MockUsb2Snes| {
+            let requests = &server.state().requests;
+            requests.iter().filter(|r| *r == "GetAddress").count()
+        };
+        let header_reads = reads(&server);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(reads(&server), header_reads);
+
+        // Switching to a layout it can read resumes polling
+        poller.set_layout("SMW", layout());
+        thread::sleep(Duration::from_millis(100));
+        let events: Vec<_> = poller.events().collect();
+        assert!(
+            matches!(events[0], PollEvent::LayoutChecked(Ok(()))),
+            "{events:?}"
+        );
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, PollEvent::Sample(_))));
+        let requests = &server.state().requests;
+        assert_eq!(requests.iter().filter(|r| *r == "Attach").count(), 1);
+        fs::remove_file(last_device).unwrap();
+    }
+
+    #[test]
+    fn reports_why_it_cannot_attach() {
+        // Nothing listens on a port freed right away
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let endpoint = USB2SnesEndpoint {
+            address: String::from("127.0.0.1"),
+            port,
+        };
+        assert!(matches!(
+            source(endpoint, None).connect(),
+            Err(ConnectionError::WebsocketUnavailable(_))
+        ));
+
+        let server = MockUsb2Snes::start(&[]);
+        let mut source = source(server.endpoint(), Some("bsnes"));
+        assert_eq!(source.connect(), Err(ConnectionError::NoDevice));
+        server.state().devices = vec![String::from("SD2SNES COM3"), String::from("Snes9x")];
+        assert_eq!(
+            source.connect(),
+            Err(ConnectionError::NoMatchingDevice(String::from("bsnes")))
+        );
+        assert_eq!(source.status(), SourceStatus::Disconnected);
+
+        // A device selected at runtime wins over the configured name
+        source.select_device("SD2SNES COM3");
+        source.connect().unwrap();
+        assert_eq!(server.state().attached.as_deref(), Some("SD2SNES COM3"));
+        fs::remove_file(source.last_device_path.unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reconnects_after_losing_the_device() {
+        let server = MockUsb2Snes::start(&["SD2SNES COM3"]);
+        server.state().write(0xF50DA2, &[0x80]);
+        let source = Box::new(source(server.endpoint(), None));
+        let last_device = source.last_device_path.clone().unwrap();
+        let poller =
+            Poller::start(source, "SMW", layout(), &PollingConfig { rate: 200.0 }).unwrap();
+
+        let states = connection_changes(&poller, |states| states.iter().any(is_attached));
+        assert!(states.iter().any(is_attached), "{states:?}");
+
+        server.drop_connections();
+        let states = connection_changes(&poller, |states| states.last().is_some_and(is_attached));
+        assert!(
+            matches!(
+                states[..],
+                [
+                    ConnectionState::Degraded {
+                        failed_reads: 1,
+                        ..
+                    },
+                    ConnectionState::Degraded {
+                        failed_reads: 2,
+                        ..
+                    },
+                    ConnectionState::Disconnected {
+                        reason: Some(ConnectionError::ReadFailed(_)),
+                        ..
+                    },
+                    ConnectionState::Attached { .. },
+                ]
+            ),
+            "{states:?}"
+        );
+        let requests = &server.state().requests;
+        assert_eq!(requests.iter().filter(|r| *r == "Attach").count(), 2);
+        fs::remove_file(last_device).unwrap();
+    }
+
+    #[test]
+    fn backs_off_when_reads_fail_after_attaching() {
+        // Attaching works, but every read fails
+        let server = MockUsb2Snes::start(&["SD2SNES COM3"]);
+        server.state().fail_reads = true;
+        let source = Box::new(source(server.endpoint(), None));
+        let last_device = source.last_device_path.clone().unwrap();
+        let poller = Poller::start(source, "SMW", layout(), &PollingConfig { rate: 200.0 });
+
+        thread::sleep(Duration::from_millis(600));
+        drop(poller);
+        // Attached at once, again right away, then after 250ms: not every 3 polls
+        let requests = &server.state().requests;
+        let attaches = requests.iter().filter(|r| *r == "Attach").count();
+        assert!((2..=4).contains(&attaches), "{attaches} attaches");
+        fs::remove_file(last_device).unwrap();
+    }
+
+    #[test]
+    fn chooses_the_preferred_device() {
+        let devices = vec![String::from("SD2SNES COM3"), String::from("Snes9x")];
+        let choose = |selected, name, last| choose_device(&devices, selected, name, last);
+
+        assert_eq!(choose(None, None, None).unwrap(), "SD2SNES COM3");
+        assert_eq!(choose(None, None, Some("Snes9x")).unwrap(), "Snes9x");
+        assert_eq!(
+            choose(None, None, Some("RetroArch")).unwrap(),
+            "SD2SNES COM3"
+        );
+        assert_eq!(choose(None, Some("snes9"), None).unwrap(), "Snes9x");
+        assert_eq!(
+            choose(Some("SD2SNES COM3"), Some("snes9x"), None).unwrap(),
+            "SD2SNES COM3"
+        );
+        assert_eq!(
+            choose(None, Some("bsnes"), Some("Snes9x")),
+            Err(ConnectionError::NoMatchingDevice(String::from("bsnes")))
+        );
+        assert_eq!(
+            choose_device(&[], None, Some("bsnes"), None),
+            Err(ConnectionError::NoDevice)
+        );
+    }
+}
@@ -1,34 +1,84 @@
-use crate::controller::button_state::ButtonState;
 use crate::controller::controller_impl::ControllerData;
+use crate::controller::port::{DeviceKind, PortState};
+use crate::controller::rom_header::RomHeader;
 
 use crate::configuration::AppConfig;
-use crate::skins::skin::Skin;
+use crate::input_source::{
+    connection::ConnectionState,
+    poller::{PollEvent, PolledSample, Poller},
+    InputSource,
+};
+use crate::latency::LatencyStatistics;
+use crate::recording::{Recorder, RecordingConfig};
+use crate::skins::light_gun_skin::LightGunSkin;
+use crate::skins::mouse_skin::MouseSkin;
+use crate::skins::skin::{Arrangement, PortSkin, Skin};
+use crate::statistics::SessionStatistics;
+use crate::status::StatusLayer;
+use crate::timeline::Timeline;
 use ggez::{
     conf, event,
-    graphics::{self, Color, DrawParam, Text, TextFragment},
+    graphics::{self, Color, DrawParam, Quad, Rect, Text},
     input::keyboard::KeyCode,
     Context, GameResult,
 };
-use rusb2snes::{SyncClient, USB2SnesEndpoint};
-use std::error::Error;
+use std::{
+    error::Error,
+    path::Path,
+    time::{Duration, Instant},
+};
 // use winit::keyboard::{Key, NamedKey};
 
 pub const APP_NAME: &str = "Snes Input Display";
+/// How far the Left/Right keys move a replay.
+const SEEK_OFFSET: Duration = Duration::from_secs(5);
 
 pub struct InputViewer {
     controller: ControllerData,
-    skin: Skin,
-    client: Option<SyncClient>,
-    events: ButtonState,
+    joypad_skin: Skin,
+    mouse_skin: Option<MouseSkin>,
+    light_gun_skin: Option<LightGunSkin>,
+    /// One skin instance per port of the current layout.
+    skins: Vec<PortSkin>,
+    arrangement: Arrangement,
+    poller: Poller,
+    /// Whether the source replays a recording, and has playback controls.
+    replay: bool,
+    /// Last polled state of each port.
+    events: Vec<PortState>,
     error_message: Option<String>,
     prev_error_message: Option<String>,
+    status: StatusLayer,
     window_title: String,
-    endpoint: USB2SnesEndpoint,
+    recording_config: RecordingConfig,
+    recorder: Option<Recorder>,
+    /// Title of the running ROM, written to recordings.
+    rom_title: Option<String>,
+    statistics: Vec<SessionStatistics>,
+    show_statistics: bool,
+    latency: LatencyStatistics,
+    show_latency: bool,
+    /// Devices the source can attach to, and the one attached.
+    devices: Vec<String>,
+    device: Option<String>,
+    show_devices: bool,
+    timeline: Option<Timeline>,
 }
 
 impl InputViewer {
-    pub fn new(ctx: &mut Context, config: AppConfig) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        ctx: &mut Context,
+        config: AppConfig,
+        mut source: Box<dyn InputSource>,
+    ) -> Result<Self, Box<dyn Error>> {
         let controller = ControllerData::new(&config.controller)?;
+        let replay = source.playback().is_some();
+        let poller = Poller::start(
+            source,
+            &controller.layout_name,
+            controller.current_layout.clone(),
+            &config.polling.unwrap_or_default(),
+        )?;
 
         let skin = Skin::new(
             &config.skin.skins_path,
@@ -36,93 +86,414 @@ impl InputViewer {
             &config.skin.skin_theme.to_lowercase(),
             ctx,
         )?;
+        let mouse_skin = match config.skin.mouse {
+            Some(ref mouse) => Some(MouseSkin::new(
+                &config.skin.skins_path,
+                &mouse.skin_name,
+                &mouse.skin_theme.to_lowercase(),
+                ctx,
+            )?),
+            None => None,
+        };
+        let light_gun_skin = match config.skin.light_gun {
+            Some(ref gun) => Some(LightGunSkin::new(
+                &config.skin.skins_path,
+                &gun.skin_name,
+                &gun.skin_theme.to_lowercase(),
+                ctx,
+            )?),
+            None => None,
+        };
+        for (device, loaded, section) in [
+            (DeviceKind::Mouse, mouse_skin.is_some(), "[skin.mouse]"),
+            (
+                DeviceKind::LightGun,
+                light_gun_skin.is_some(),
+                "[skin.light_gun]",
+            ),
+        ] {
+            if let (false, Some(name)) = (loaded, controller.layout_with_device(device)) {
+                return Err(format!(
+                    "Layout {name} has a {device:?} port but no {section} is configured"
+                )
+                .into());
+            }
+        }
 
-        // Set the window size
-        ctx.gfx.set_mode(conf::WindowMode {
-            width: skin.background.image.width() as f32,
-            height: skin.background.height,
-            resizable: true,
-            ..Default::default()
-        })?;
+        let timeline = match config.timeline {
+            Some(ref t) if t.enabled => Some(Timeline::new(t)?),
+            _ => None,
+        };
 
-        let endpoint = config.usb2snes.unwrap_or_default();
         let window_title = format!("{} - {}", APP_NAME, controller.layout_name);
         ctx.gfx.set_window_title(&window_title);
 
-        Ok(Self {
+        let mut input_viewer = Self {
             controller,
-            skin,
-            client: None,
-            events: ButtonState::default(),
+            joypad_skin: skin,
+            mouse_skin,
+            light_gun_skin,
+            skins: Vec::new(),
+            arrangement: config.skin.arrangement,
+            poller,
+            replay,
+            events: Vec::new(),
             error_message: None,
             prev_error_message: None,
+            status: StatusLayer::new(&config.status.unwrap_or_default()),
             window_title,
-            endpoint,
+            recording_config: config.recording.unwrap_or_default(),
+            recorder: None,
+            rom_title: None,
+            statistics: Vec::new(),
+            show_statistics: false,
+            latency: LatencyStatistics::default(),
+            show_latency: false,
+            devices: Vec::new(),
+            device: None,
+            show_devices: false,
+            timeline,
+        };
+        input_viewer.apply_layout_ports(ctx)?;
+        Ok(input_viewer)
+    }
+
+    /// Gives every port of the current layout its own skin instance and statistics, and resizes
+    /// the window to fit them.
+    fn apply_layout_ports(&mut self, ctx: &mut Context) -> GameResult {
+        self.poller.set_layout(
+            &self.controller.layout_name,
+            self.controller.current_layout.clone(),
+        );
+        let ports = self.controller.current_layout.ports.len();
+        self.skins = self
+            .controller
+            .current_layout
+            .ports
+            .iter()
+            .map(|port| {
+                let device_skin = match port.device() {
+                    DeviceKind::Joypad => None,
+                    DeviceKind::Mouse => self.mouse_skin.clone().map(Box::new).map(PortSkin::Mouse),
+                    DeviceKind::LightGun => self
+                        .light_gun_skin
+                        .clone()
+                        .map(Box::new)
+                        .map(PortSkin::LightGun),
+                };
+                device_skin.unwrap_or_else(|| PortSkin::Joypad(self.joypad_skin.clone()))
+            })
+            .collect();
+        self.statistics
+            .resize_with(ports, SessionStatistics::default);
+
+        // Set the window size, leaving room for the timeline and status under the skins
+        let (width, height) = self.skins_size();
+        let timeline_height = self.timeline.as_ref().map_or(0.0, Timeline::height);
+        ctx.gfx.set_mode(conf::WindowMode {
+            width,
+            height: height + timeline_height + self.status.height(),
+            resizable: true,
+            ..Default::default()
         })
     }
 
-    fn connect(&mut self) -> Result<Option<SyncClient>, Box<dyn Error>> {
-        let client = match SyncClient::connect(&self.endpoint) {
-            Ok(mut s) => {
-                s.set_name(String::from(APP_NAME))?;
-                match s.list_device() {
-                    Ok(l) => {
-                        if !l.is_empty() {
-                            s.attach(&l[0])?;
-                            let msg = format!("Attached to {}", &l[0]);
-                            println!("{}", msg);
-                        } else {
-                            self.error_message =
-                                Some("Not attached to usb2snes compatible endpoint".to_string());
+    /// Size of the area covered by the skins of every port.
+    fn skins_size(&self) -> (f32, f32) {
+        let sizes = self.skins.iter().map(|skin| {
+            (
+                skin.background().image.width() as f32,
+                skin.background().height,
+            )
+        });
+        match self.arrangement {
+            Arrangement::Horizontal => {
+                sizes.fold((0.0, 0.0), |(w, h), (sw, sh)| (w + sw, h.max(sh)))
+            }
+            Arrangement::Vertical => sizes.fold((0.0, 0.0), |(w, h), (sw, sh)| (w.max(sw), h + sh)),
+        }
+    }
+
+    /// Position of the skin of `port` in the window, after the skins of the previous ports.
+    fn port_origin(&self, port: usize) -> [f32; 2] {
+        let previous = self.skins[..port].iter().map(PortSkin::background);
+        match self.arrangement {
+            Arrangement::Horizontal => [previous.map(|b| b.image.width() as f32).sum(), 0.0],
+            Arrangement::Vertical => [0.0, previous.map(|b| b.height).sum()],
+        }
+    }
+
+    /// Handles everything the polling thread did since the previous update. Every sample is
+    /// recorded under the layout it was read with. Samples of the current layout are fed to the
+    /// statistics and timeline, the last one is displayed.
+    fn handle_poll_events(&mut self, ctx: &mut Context) -> GameResult {
+        for event in self.poller.events().collect::<Vec<_>>() {
+            match event {
+                PollEvent::Sample(sample) => {
+                    self.error_message = None;
+                    self.latency.update(&sample);
+                    self.record(&sample);
+                    // Read before a layout switch, its ports don't match the skins anymore
+                    if sample.layout != self.controller.layout_name {
+                        continue;
+                    }
+                    let joypads = sample.ports.iter().map(PortState::joypad);
+                    for (statistics, state) in self.statistics.iter_mut().zip(joypads) {
+                        if let Some(state) = state {
+                            statistics.update(sample.polled_at, state);
                         }
                     }
-                    Err(_) => {
-                        println!("No device available");
-                        return Ok(Some(s));
+                    // The timeline follows player 1
+                    if let (Some(timeline), Some(state)) = (
+                        self.timeline.as_mut(),
+                        sample.ports.first().and_then(PortState::joypad),
+                    ) {
+                        timeline.push(sample.polled_at, state);
                     }
+                    self.events = sample.ports;
+                }
+                PollEvent::Attached {
+                    description,
+                    devices,
+                    info,
+                    rom_header,
+                } => {
+                    println!("Reading inputs from {description}");
+                    self.devices = devices;
+                    self.rom_title = match rom_header {
+                        Ok(Some(ref header)) => Some(header.title.clone()),
+                        _ => None,
+                    };
+                    self.status.attached(info, self.rom_title.clone());
+                    self.record_rom();
+                    self.select_layout_for_rom(ctx, rom_header)?;
+                }
+                PollEvent::ConnectionChanged(state) => self.connection_changed(state),
+                PollEvent::LayoutChecked(Ok(())) => {}
+                PollEvent::LayoutChecked(Err(reason)) => {
+                    println!("{reason}");
+                    self.events.clear();
+                    self.error_message = Some(reason);
                 }
-                Some(s)
-            }
-            Err(_) => {
-                self.error_message = Some("Not connected to usb2snes websocket".to_string());
-                None
             }
+        }
+        Ok(())
+    }
+
+    /// Updates the status badge, and logs why the source is disconnected or degraded.
+    fn connection_changed(&mut self, state: ConnectionState) {
+        self.latency.connection_changed(&state);
+        self.device = match state {
+            ConnectionState::Attached { ref device }
+            | ConnectionState::Degraded { ref device, .. } => Some(device.clone()),
+            _ => None,
         };
+        match state {
+            ConnectionState::Attached { .. } | ConnectionState::Connecting { .. } => {}
+            ConnectionState::Disconnected { reason: None, .. } => {}
+            ConnectionState::Disconnected { .. } => {
+                self.events.clear();
+                println!("{state}");
+            }
+            ConnectionState::Degraded { .. } => println!("{state}"),
+        }
+        self.status.connection_changed(state);
+    }
 
-        Ok(client)
+    /// Attaches to the device listed after the current one.
+    fn select_next_device(&mut self) {
+        if self.devices.len() < 2 {
+            return;
+        }
+        let current = self
+            .device
+            .as_ref()
+            .and_then(|device| self.devices.iter().position(|d| d == device));
+        let next = current.map_or(0, |i| (i + 1) % self.devices.len());
+        println!("Switching to device {}", self.devices[next]);
+        self.poller.select_device(self.devices[next].clone());
+    }
+
+    /// Devices the source can attach to, the attached one marked with a `>`.
+    fn devices_summary(&self) -> String {
+        let mut summary = String::from("Devices (Tab: next)\n");
+        if self.devices.is_empty() {
+            summary.push_str("  none listed\n");
+        }
+        for device in &self.devices {
+            let marker = if self.device.as_ref() == Some(device) {
+                '>'
+            } else {
+                ' '
+            };
+            summary.push_str(&format!("{marker} {device}\n"));
+        }
+        summary
+    }
+
+    /// Switches to the layout matching the header of the running ROM, when the source could
+    /// read it.
+    fn select_layout_for_rom(
+        &mut self,
+        ctx: &mut Context,
+        rom_header: Result<Option<RomHeader>, String>,
+    ) -> GameResult {
+        match rom_header {
+            Ok(Some(header)) => {
+                println!("Running {header}");
+                if self.controller.select_layout_for_rom(&header) {
+                    println!("Switched to layout {}", self.controller.layout_name);
+                    self.apply_layout_ports(ctx)?;
+                    self.update_title();
+                }
+            }
+            Ok(None) => println!("No valid ROM header found"),
+            Err(e) => self.error_message = Some(format!("Could not read the ROM header: {e}")),
+        }
+        Ok(())
     }
 
     fn update_title(&mut self) {
         self.window_title = format!("{} - {}", APP_NAME, self.controller.layout_name);
+        if self.replay {
+            if let Some(playback) = self.poller.source().playback() {
+                let label = playback.state_label();
+                self.window_title.push_str(&format!(" [Replay {label}]"));
+            }
+        }
+        if self.recorder.is_some() {
+            self.window_title.push_str(" [REC]");
+        }
+    }
+
+    /// Space pauses, Left/Right seek, comma/period step one sample and Up/Down change the
+    /// speed of a replay.
+    fn handle_playback_keys(&mut self, ctx: &Context) {
+        if !self.replay {
+            return;
+        }
+        let mut source = self.poller.source();
+        let Some(playback) = source.playback() else {
+            return;
+        };
+        let keyboard = &ctx.keyboard;
+        if keyboard.is_key_just_pressed(KeyCode::Space) {
+            playback.toggle_pause();
+        } else if keyboard.is_key_just_pressed(KeyCode::Left) {
+            playback.seek(SEEK_OFFSET, true);
+        } else if keyboard.is_key_just_pressed(KeyCode::Right) {
+            playback.seek(SEEK_OFFSET, false);
+        } else if keyboard.is_key_just_pressed(KeyCode::Comma) {
+            playback.step(true);
+        } else if keyboard.is_key_just_pressed(KeyCode::Period) {
+            playback.step(false);
+        } else if keyboard.is_key_just_pressed(KeyCode::Down) {
+            playback.change_speed(true);
+        } else if keyboard.is_key_just_pressed(KeyCode::Up) {
+            playback.change_speed(false);
+        }
+    }
+
+    /// Starts writing every polled `ButtonState` to the input log at `path`.
+    pub fn start_recording(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.recorder = Some(Recorder::start(path, &self.controller.layout_name)?);
+        self.record_rom();
+        self.update_title();
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.stop() {
+                self.error_message = Some(format!("Could not save recording: {e}"));
+            }
+        }
+        self.update_title();
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+            return;
+        }
+        let started = self
+            .recording_config
+            .new_session_path()
+            .and_then(|path| self.start_recording(&path));
+        if let Err(e) = started {
+            self.error_message = Some(format!("Could not start recording: {e}"));
+        }
+    }
+
+    /// Statistics of every port, with a heading per player when there are several.
+    fn statistics_summary(&self) -> String {
+        match &self.statistics[..] {
+            [single] => single.summary(),
+            ports => ports
+                .iter()
+                .enumerate()
+                .map(|(port, s)| format!("Player {}\n{}", port + 1, s.summary()))
+                .collect(),
+        }
+    }
+
+    /// Tags the recording with the title of the running ROM, for exports.
+    fn record_rom(&mut self) {
+        let (Some(recorder), Some(title)) = (self.recorder.as_mut(), self.rom_title.as_ref())
+        else {
+            return;
+        };
+        if let Err(e) = recorder.set_rom(title) {
+            self.error_message = Some(format!("Recording stopped: {e}"));
+            self.recorder = None;
+            self.update_title();
+        }
+    }
+
+    fn record(&mut self, sample: &PolledSample) {
+        let Some(ref mut recorder) = self.recorder else {
+            return;
+        };
+        let recorded = recorder.record(
+            sample.sequence,
+            sample.polled_at,
+            &sample.layout,
+            sample.frame,
+            &sample.ports,
+        );
+        if let Err(e) = recorded {
+            self.error_message = Some(format!("Recording stopped: {e}"));
+            self.recorder = None;
+            self.update_title();
+        }
     }
 }
 
 impl event::EventHandler for InputViewer {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.handle_playback_keys(ctx);
         if ctx.keyboard.is_key_just_released(KeyCode::J) {
             self.controller.get_next_layout();
-            self.window_title = format!("{} - {}", APP_NAME, self.controller.layout_name);
+            self.apply_layout_ports(ctx)?;
+            self.update_title();
         } else if ctx.keyboard.is_key_just_released(KeyCode::K) {
             self.controller.get_prev_layout();
+            self.apply_layout_ports(ctx)?;
             self.update_title();
-        } else {
-            match self.client {
-                Some(ref mut c) => match self.controller.current_addresses.pushed(c) {
-                    Ok(e) => {
-                        self.events = e;
-                        self.error_message = None;
-                    }
-                    Err(_) => {
-                        self.events = ButtonState::default();
-                        self.client = None;
-                    }
-                },
-                None => match self.connect() {
-                    Ok(c) => self.client = c,
-                    Err(_) => self.client = None,
-                },
-            };
+        } else if ctx.keyboard.is_key_just_released(KeyCode::R) {
+            self.toggle_recording();
+        } else if ctx.keyboard.is_key_just_released(KeyCode::S) {
+            self.show_statistics = !self.show_statistics;
+        } else if ctx.keyboard.is_key_just_released(KeyCode::L) {
+            self.show_latency = !self.show_latency;
+        } else if ctx.keyboard.is_key_just_released(KeyCode::D) {
+            self.show_devices = !self.show_devices;
+        } else if ctx.keyboard.is_key_just_released(KeyCode::Tab) {
+            self.select_next_device();
+        } else if ctx.keyboard.is_key_just_released(KeyCode::I) {
+            self.status.show_info = !self.status.show_info;
         }
+        self.handle_poll_events(ctx)?;
 
         if self.error_message != self.prev_error_message {
             let deb = match &self.error_message {
@@ -132,36 +503,73 @@ impl event::EventHandler for InputViewer {
             println!("{}", deb);
             self.prev_error_message = self.error_message.clone();
         }
-        let window_title = format!("{} - {}", APP_NAME, self.controller.layout_name);
-        ctx.gfx.set_window_title(&window_title);
+        if self.replay {
+            self.update_title();
+        }
+        ctx.gfx.set_window_title(&self.window_title);
         Ok(())
     }
 
+    fn quit_event(&mut self, _ctx: &mut Context) -> GameResult<bool> {
+        self.stop_recording();
+        print!("{}", self.statistics_summary());
+        Ok(false)
+    }
+
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         let mut canvas = graphics::Canvas::from_frame(ctx, None);
 
-        // draw background
-        canvas.draw(&self.skin.background.image, DrawParam::new());
+        // draw the background and inputs of every port
+        for (port, skin) in self.skins.iter().enumerate() {
+            skin.draw(&mut canvas, self.port_origin(port), self.events.get(port));
+        }
+
+        // draw input history under the skins
+        if let Some(ref timeline) = self.timeline {
+            let (width, height) = self.skins_size();
+            timeline.draw(&mut canvas, height, width, Instant::now());
+        }
 
-        // Draw inputs
-        self.events.iter().for_each(|event| {
-            let button_image = &self.skin.buttons[event].image;
+        // draw statistics and latency panel
+        let mut panel = String::new();
+        if self.show_statistics {
+            panel.push_str(&self.statistics_summary());
+        }
+        if self.show_latency {
+            panel.push_str(&self.latency.summary());
+        }
+        if self.show_devices {
+            panel.push_str(&self.devices_summary());
+        }
+        if !panel.is_empty() {
+            let text = Text::new(panel);
+            let size = text.measure(ctx)?;
+            let origin = [4.0, 24.0];
             canvas.draw(
-                button_image,
-                DrawParam::default().dest(self.skin.buttons[event].rect.point()),
+                &Quad,
+                DrawParam::default()
+                    .dest_rect(Rect::new(
+                        origin[0] - 4.0,
+                        origin[1] - 4.0,
+                        size.x + 8.0,
+                        size.y + 8.0,
+                    ))
+                    .color(Color::from_rgba(0, 0, 0, 180)),
             );
-        });
-
-        // draw error message
-        if let Some(ref msg) = self.error_message {
-            let text = Text::new(TextFragment {
-                text: msg.to_string(),
-                color: Some(Color::RED),
-                ..Default::default()
-            });
-            canvas.draw(&text, DrawParam::default());
+            canvas.draw(&text, DrawParam::default().dest(origin));
         }
 
+        // draw connection status and error message
+        let (width, height) = self.skins_size();
+        let timeline_height = self.timeline.as_ref().map_or(0.0, Timeline::height);
+        self.status.draw(
+            ctx,
+            &mut canvas,
+            width,
+            height + timeline_height,
+            self.error_message.as_deref(),
+        )?;
+
         canvas.finish(ctx)
     }
 }
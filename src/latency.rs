@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::controller::{controller_impl::ControllerLayout, frame_counter::FrameCount};
+use crate::input_source::{
+    connection::ConnectionState,
+    poller::{PollEvent, PolledSample, Poller, PollingConfig},
+    InputSource,
+};
+
+/// Number of durations kept by each rolling window, ten seconds of polls at 60 Hz.
+const WINDOW: usize = 600;
+/// Upper bounds of the histogram buckets, in milliseconds. The last bucket is unbounded.
+const BUCKETS_MS: [u64; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+const HISTOGRAM_WIDTH: usize = 40;
+pub const DIAGNOSE_DURATION: Duration = Duration::from_secs(10);
+
+/// The last `WINDOW` measured durations.
+#[derive(Debug, Default)]
+pub struct RollingDurations {
+    durations: VecDeque<Duration>,
+}
+
+impl RollingDurations {
+    pub fn push(&mut self, duration: Duration) {
+        if self.durations.len() == WINDOW {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(duration);
+    }
+
+    pub fn len(&self) -> usize {
+        self.durations.len()
+    }
+
+    /// Nearest rank percentile, `None` until a duration is measured.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.durations.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+    }
+
+    /// Count of durations in every bucket of `BUCKETS_MS`, plus the overflow bucket.
+    pub fn histogram(&self) -> [usize; BUCKETS_MS.len() + 1] {
+        let mut counts = [0; BUCKETS_MS.len() + 1];
+        for duration in &self.durations {
+            let bucket = BUCKETS_MS
+                .iter()
+                .position(|&bound| *duration < Duration::from_millis(bound))
+                .unwrap_or(BUCKETS_MS.len());
+            counts[bucket] += 1;
+        }
+        counts
+    }
+
+    /// `p50 3.1ms  p90 4.0ms  p99 9.8ms  max 12.0ms`, or `no data`.
+    fn percentiles_line(&self) -> String {
+        let Some(max) = self.percentile(100.0) else {
+            return String::from("no data");
+        };
+        let mut line = String::new();
+        for percent in [50, 90, 99] {
+            let duration = self.percentile(percent as f64).unwrap_or_default();
+            let _ = write!(line, "p{percent} {:.1}ms  ", millis(duration));
+        }
+        let _ = write!(line, "max {:.1}ms", millis(max));
+        line
+    }
+
+    fn histogram_lines(&self) -> String {
+        let counts = self.histogram();
+        let largest = counts.iter().copied().max().unwrap_or(0).max(1);
+        let mut lines = String::new();
+        for (bucket, count) in counts.into_iter().enumerate() {
+            let label = match BUCKETS_MS.get(bucket) {
+                Some(bound) => format!("<{bound:>4}ms"),
+                None => format!(">={:>3}ms", BUCKETS_MS[BUCKETS_MS.len() - 1]),
+            };
+            let bar = "#".repeat(count * HISTOGRAM_WIDTH / largest);
+            let _ = writeln!(lines, "  {label} {bar:<HISTOGRAM_WIDTH$} {count}");
+        }
+        lines
+    }
+}
+
+/// Frames the polls missed or read twice, according to the in-game frame counter.
+#[derive(Debug, Default)]
+pub struct FrameStatistics {
+    /// Frames between two consecutive polls that no poll read.
+    pub skipped: u32,
+    /// Polls that read the same frame as the previous poll.
+    pub duplicated: u32,
+    last_frame: Option<FrameCount>,
+}
+
+impl FrameStatistics {
+    pub fn update(&mut self, frame: FrameCount) {
+        match self.last_frame.and_then(|last| frame.frames_since(last)) {
+            Some(0) => self.duplicated += 1,
+            Some(elapsed) => self.skipped += elapsed - 1,
+            // First frame, or the game reset its counter
+            None => {}
+        }
+        self.last_frame = Some(frame);
+    }
+}
+
+/// Timing of the read path: the round trip of every memory read, and the interval between two
+/// consecutive samples.
+#[derive(Debug, Default)]
+pub struct LatencyStatistics {
+    pub reads: RollingDurations,
+    pub intervals: RollingDurations,
+    pub frames: FrameStatistics,
+    /// Times the device was lost after attaching.
+    pub disconnects: u32,
+    last_polled_at: Option<Instant>,
+    attached: bool,
+}
+
+impl LatencyStatistics {
+    pub fn update(&mut self, sample: &PolledSample) {
+        for read_time in &sample.read_times {
+            self.reads.push(*read_time);
+        }
+        if let Some(last) = self.last_polled_at {
+            self.intervals
+                .push(sample.polled_at.saturating_duration_since(last));
+        }
+        self.last_polled_at = Some(sample.polled_at);
+        if let Some(frame) = sample.frame {
+            self.frames.update(frame);
+        }
+    }
+
+    /// Counts the disconnects, failed attempts to attach are not.
+    pub fn connection_changed(&mut self, state: &ConnectionState) {
+        match state {
+            ConnectionState::Attached { .. } => self.attached = true,
+            ConnectionState::Disconnected { .. } if self.attached => {
+                self.disconnects += 1;
+                self.attached = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Percentiles of the read round trips and sample intervals, one line each, and the
+    /// dropped frames when the layout has a frame counter.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "read     {}\ninterval {}\n",
+            self.reads.percentiles_line(),
+            self.intervals.percentiles_line()
+        );
+        if self.frames.last_frame.is_some() {
+            let _ = writeln!(
+                summary,
+                "frames   {} skipped  {} duplicated",
+                self.frames.skipped, self.frames.duplicated
+            );
+        }
+        if self.disconnects > 0 {
+            let _ = writeln!(summary, "lost     {} disconnects", self.disconnects);
+        }
+        summary
+    }
+
+    /// The summary followed by a histogram of both measures.
+    pub fn report(&self) -> String {
+        format!(
+            "{}\nRead round trips ({} reads)\n{}\nSample intervals ({} samples)\n{}",
+            self.summary(),
+            self.reads.len(),
+            self.reads.histogram_lines(),
+            self.intervals.len(),
+            self.intervals.histogram_lines()
+        )
+    }
+}
+
+/// Polls `source` for `duration` without opening a window, and returns the measured timings.
+/// Disconnects are counted and polling goes on, it fails only if the source never attached.
+pub fn diagnose(
+    source: Box<dyn InputSource>,
+    layout_name: &str,
+    layout: ControllerLayout,
+    config: &PollingConfig,
+    duration: Duration,
+) -> Result<LatencyStatistics, Box<dyn Error>> {
+    let poller = Poller::start(source, layout_name, layout, config)?;
+    let mut statistics = LatencyStatistics::default();
+    let end = Instant::now() + duration;
+    let mut attached = false;
+    let mut last_error = None;
+    while Instant::now() < end {
+        thread::sleep(Duration::from_millis(50));
+        for event in poller.events() {
+            match event {
+                PollEvent::Sample(sample) => statistics.update(&sample),
+                PollEvent::Attached { description, .. } => {
+                    attached = true;
+                    println!("Reading inputs from {description}")
+                }
+                PollEvent::ConnectionChanged(state) => {
+                    println!("{state}");
+                    statistics.connection_changed(&state);
+                    if let ConnectionState::Disconnected {
+                        reason: Some(reason),
+                        ..
+                    } = state
+                    {
+                        last_error = Some(reason);
+                    }
+                }
+                PollEvent::LayoutChecked(Ok(())) => {}
+                PollEvent::LayoutChecked(Err(reason)) => println!("{reason}"),
+            }
+        }
+    }
+    println!("{}", poller.connection());
+    match (attached, last_error) {
+        (false, Some(error)) => Err(error.into()),
+        (false, None) => Err("the source never attached".into()),
+        (true, _) => Ok(statistics),
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_source::connection::ConnectionError;
+
+    #[test]
+    fn rolling_percentiles_and_histogram() {
+        let start = Instant::now();
+        let mut statistics = LatencyStatistics::default();
+        assert_eq!(statistics.reads.percentile(50.0), None);
+        assert!(statistics.summary().contains("no data"));
+
+        for (millis, read) in [(0, 3), (16, 3), (33, 5), (50, 40), (66, 3)] {
+            statistics.update(&PolledSample {
+                sequence: 0,
+                polled_at: start + Duration::from_millis(millis),
+                layout: String::new(),
+                frame: None,
+                ports: Vec::new(),
+                read_times: vec![Duration::from_millis(read)],
+            });
+        }
+        assert_eq!(statistics.reads.len(), 5);
+        assert_eq!(statistics.intervals.len(), 4);
+        assert_eq!(
+            statistics.reads.percentile(50.0),
+            Some(Duration::from_millis(3))
+        );
+        assert_eq!(
+            statistics.reads.percentile(100.0),
+            Some(Duration::from_millis(40))
+        );
+        assert_eq!(
+            statistics.intervals.percentile(0.0),
+            Some(Duration::from_millis(16))
+        );
+        assert_eq!(statistics.reads.histogram(), [0, 0, 3, 1, 0, 0, 1, 0, 0]);
+        assert_eq!(
+            statistics.intervals.histogram(),
+            [0, 0, 0, 0, 0, 4, 0, 0, 0]
+        );
+        assert!(statistics.report().contains("Sample intervals (4 samples)"));
+        assert!(!statistics.summary().contains("skipped"));
+
+        // A one byte counter, wrapping around between polls then reset by the game
+        let mut frames = FrameStatistics::default();
+        for value in [0xFD, 0xFE, 0xFE, 0x01, 0x02, 0x80, 0x81, 0x10] {
+            frames.update(FrameCount { value, bytes: 1 });
+        }
+        assert_eq!(frames.duplicated, 1);
+        assert_eq!(frames.skipped, 2 + 0x7D);
+
+        let mut window = RollingDurations::default();
+        for millis in 0..WINDOW as u64 + 10 {
+            window.push(Duration::from_millis(millis));
+        }
+        assert_eq!(window.len(), WINDOW);
+        assert_eq!(window.percentile(0.0), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn counts_disconnects_after_attaching() {
+        let disconnected = || ConnectionState::Disconnected {
+            reason: Some(ConnectionError::NoDevice),
+            attempts: 1,
+            retry_at: Instant::now(),
+        };
+        let attached = || ConnectionState::Attached {
+            device: String::from("SD2SNES COM3"),
+        };
+        let mut statistics = LatencyStatistics::default();
+        for state in [
+            // Failing to attach in the first place is not a disconnect
+            ConnectionState::Connecting { attempt: 1 },
+            disconnected(),
+            attached(),
+            ConnectionState::Degraded {
+                device: String::from("SD2SNES COM3"),
+                failed_reads: 1,
+                reason: ConnectionError::ReadFailed(String::from("timeout")),
+            },
+            disconnected(),
+            ConnectionState::Connecting { attempt: 1 },
+            disconnected(),
+            attached(),
+            disconnected(),
+        ] {
+            statistics.connection_changed(&state);
+        }
+        assert_eq!(statistics.disconnects, 2);
+        assert!(statistics.summary().contains("2 disconnects"));
+    }
+}
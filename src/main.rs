@@ -1,25 +1,68 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod cli;
 mod configuration;
 mod controller;
+mod input_source;
 mod input_viewer;
+mod latency;
+mod movie;
+mod recording;
 mod skins;
+mod statistics;
+mod status;
+mod timeline;
+use cli::CliArgs;
+use input_source::{replay::ReplaySource, usb2snes::Usb2SnesSource, InputSource};
 use input_viewer::{InputViewer, APP_NAME};
 use std::{env, error::Error};
 
 use ggez::{event, ContextBuilder, GameResult};
 
 use configuration::AppConfig;
+use controller::controller_impl::ControllerData;
 
 fn main() -> Result<GameResult, Box<dyn Error>> {
     /* Setup Configs */
-    let config_path = env::args().nth(1);
-    let app_config = AppConfig::new(config_path)?;
+    let cli_args = CliArgs::parse(env::args().skip(1))?;
+    if let Some((ref input_log, ref movie)) = cli_args.export_bk2 {
+        movie::bk2::export_file(input_log, movie, cli_args.rom_path.as_deref())?;
+        return Ok(Ok(()));
+    }
+    let mut app_config = AppConfig::new(cli_args.config_path)?;
+
+    let source: Box<dyn InputSource> = match cli_args.replay_path {
+        Some(ref path) => Box::new(ReplaySource::open(path, cli_args.player)?),
+        None => Box::new(Usb2SnesSource::new(
+            app_config.usb2snes.take().unwrap_or_default(),
+            app_config.device.take().unwrap_or_default(),
+        )),
+    };
+    if cli_args.diagnose {
+        let controller = ControllerData::new(&app_config.controller)?;
+        println!(
+            "Measuring {} for {} seconds...",
+            source.describe(),
+            latency::DIAGNOSE_DURATION.as_secs()
+        );
+        let statistics = latency::diagnose(
+            source,
+            &controller.layout_name,
+            controller.current_layout,
+            &app_config.polling.unwrap_or_default(),
+            latency::DIAGNOSE_DURATION,
+        )?;
+        print!("{}", statistics.report());
+        return Ok(Ok(()));
+    }
 
     let (mut ctx, event_loop) = ContextBuilder::new(APP_NAME, "ChTBoner")
         .add_resource_path(&app_config.skin.skins_path)
         .build()
         .expect("aieee, could not create ggez context!");
 
-    let input_viewer = InputViewer::new(&mut ctx, app_config)?;
+    let mut input_viewer = InputViewer::new(&mut ctx, app_config, source)?;
+    if let Some(path) = cli_args.record_path {
+        input_viewer.start_recording(&path)?;
+    }
     event::run(ctx, event_loop, input_viewer)
 }
@@ -0,0 +1,129 @@
+pub mod bk2;
+pub mod lsmv;
+pub mod smv;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::controller::{button_state::ButtonState, port::PortState};
+use crate::recording::input_log::Sample;
+
+/// Frame rate of an NTSC SNES, as used by emulator movie formats.
+pub const NTSC_FRAME_RATE: f64 = 21_477_272.0 / 357_366.0;
+/// Frame rate of a PAL SNES.
+pub const PAL_FRAME_RATE: f64 = 21_281_370.0 / 425_568.0;
+
+/// Inputs of a single player imported from an emulator movie.
+#[derive(Debug)]
+pub struct Movie {
+    pub frames: Vec<ButtonState>,
+    pub frame_rate: f64,
+}
+
+impl Movie {
+    /// Converts the frames into samples timed at the movie frame rate, for the replay source.
+    pub fn to_samples(&self) -> Vec<Sample> {
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(frame, &state)| Sample {
+                sequence: frame as u32,
+                elapsed: Duration::from_secs_f64(frame as f64 / self.frame_rate),
+                frame: None,
+                ports: vec![PortState::Joypad(state)],
+            })
+            .collect()
+    }
+}
+
+/// Emulator movie formats that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MovieFormat {
+    /// BizHawk
+    Bk2,
+    /// Snes9x
+    Smv,
+    /// lsnes
+    Lsmv,
+}
+
+impl MovieFormat {
+    /// Guesses the format from the file extension, `None` if it is not a known movie format.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "bk2" => Some(MovieFormat::Bk2),
+            "smv" => Some(MovieFormat::Smv),
+            "lsmv" => Some(MovieFormat::Lsmv),
+            _ => None,
+        }
+    }
+
+    /// Reads the inputs of `player` (0 based, players 3 to 5 being multitap controllers) from
+    /// the movie at `path`.
+    pub fn load(self, path: &Path, player: usize) -> Result<Movie, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        match self {
+            MovieFormat::Bk2 => bk2::read_movie(reader, player),
+            MovieFormat::Smv => smv::read_movie(reader, player),
+            MovieFormat::Lsmv => lsmv::read_movie(reader, player),
+        }
+    }
+}
+
+/// Converts timestamped samples into the port states of each frame at `frame_rate`, each frame
+/// holding the latest sample polled at or before its start. Samples tagged with the in-game
+/// frame are placed on their frame instead, so the result lines up with emulator movies.
+pub fn samples_to_frames(samples: &[Sample], frame_rate: f64) -> Vec<Vec<PortState>> {
+    if let Some(frames) = tagged_samples_to_frames(samples) {
+        return frames;
+    }
+    let Some(last) = samples.last() else {
+        return Vec::new();
+    };
+    let frame_count = (last.elapsed.as_secs_f64() * frame_rate).ceil() as usize + 1;
+
+    let mut frames = Vec::with_capacity(frame_count);
+    let mut next_sample = 0;
+    let mut ports: &[PortState] = &[];
+    for frame in 0..frame_count {
+        let frame_start = Duration::from_secs_f64(frame as f64 / frame_rate);
+        while next_sample < samples.len() && samples[next_sample].elapsed <= frame_start {
+            ports = &samples[next_sample].ports;
+            next_sample += 1;
+        }
+        frames.push(ports.to_vec());
+    }
+    frames
+}
+
+/// Frame index of every sample, counted from the frame of the first one, `None` unless every
+/// sample is tagged with a frame and the counter never goes back.
+fn frame_indexes(samples: &[Sample]) -> Option<Vec<usize>> {
+    let mut previous = samples.first()?.frame?;
+    let mut index = 0;
+    let mut indexes = Vec::with_capacity(samples.len());
+    for sample in samples {
+        let frame = sample.frame?;
+        index += frame.frames_since(previous)? as usize;
+        indexes.push(index);
+        previous = frame;
+    }
+    Some(indexes)
+}
+
+/// Places samples on the frame they were read on. Frames no sample read keep the previous
+/// state, and a frame read several times keeps its last sample.
+fn tagged_samples_to_frames(samples: &[Sample]) -> Option<Vec<Vec<PortState>>> {
+    let indexes = frame_indexes(samples)?;
+    let mut frames: Vec<Vec<PortState>> = Vec::with_capacity(indexes.last()? + 1);
+    for (sample, index) in samples.iter().zip(indexes) {
+        let previous = frames.last().cloned().unwrap_or_default();
+        frames.resize(index, previous);
+        frames.push(sample.ports.clone());
+    }
+    Some(frames)
+}
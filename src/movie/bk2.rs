@@ -0,0 +1,368 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::controller::{port::PortState, pressed::Pressed};
+use crate::movie::{samples_to_frames, Movie, NTSC_FRAME_RATE, PAL_FRAME_RATE};
+use crate::recording::Session;
+
+/// BizHawk SNES controller columns in `Input Log.txt` order: name used in the `LogKey` and
+/// mnemonic written when the button is held.
+const COLUMNS: [(Pressed, &str, char); 12] = [
+    (Pressed::Up, "Up", 'U'),
+    (Pressed::Down, "Down", 'D'),
+    (Pressed::Left, "Left", 'L'),
+    (Pressed::Right, "Right", 'R'),
+    (Pressed::Select, "Select", 's'),
+    (Pressed::Start, "Start", 'S'),
+    (Pressed::Y, "Y", 'Y'),
+    (Pressed::B, "B", 'B'),
+    (Pressed::X, "X", 'X'),
+    (Pressed::A, "A", 'A'),
+    (Pressed::L, "L", 'l'),
+    (Pressed::R, "R", 'r'),
+];
+
+/// Device of a BSNES core controller port, as numbered in its sync settings.
+const GAMEPAD: u8 = 1;
+const SUPER_MULTITAP: u8 = 4;
+/// Type of the BSNES core sync settings, checked by BizHawk when loading them.
+const SYNC_SETTINGS_TYPE: &str =
+    "BizHawk.Emulation.Cores.Nintendo.BSNES.BsnesCore+SnesSyncSettings, BizHawk.Emulation.Cores";
+
+/// Devices plugged in the left and right ports to hold `players` joypads, and the number of
+/// joypads the core then defines. Multitaps hold 4 joypads each.
+fn port_devices(players: usize) -> (u8, u8, usize) {
+    match players {
+        0..=2 => (GAMEPAD, GAMEPAD, 2),
+        3..=5 => (GAMEPAD, SUPER_MULTITAP, 5),
+        _ => (SUPER_MULTITAP, SUPER_MULTITAP, 8),
+    }
+}
+
+/// SHA1 of a ROM file as BizHawk computes it, without the 512 bytes copier header some dumps
+/// have.
+fn rom_sha1(rom: &[u8]) -> String {
+    let rom = match rom.len() % 1024 {
+        512 => &rom[512..],
+        _ => rom,
+    };
+    sha1_smol::Sha1::from(rom)
+        .digest()
+        .to_string()
+        .to_uppercase()
+}
+
+/// Converts a recorded input log into a BizHawk movie. The `rom` the session was played on,
+/// when given, lets BizHawk check the movie is played on the same ROM.
+pub fn export_file(
+    input_log: &Path,
+    output: &Path,
+    rom: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let session = Session::load(input_log)?;
+    let frames = samples_to_frames(&session.samples, NTSC_FRAME_RATE);
+    let game_name = match session.rom_title {
+        Some(ref title) => title.as_str(),
+        None => session.layouts.first().map_or("", String::as_str),
+    };
+    let sha1 = match rom {
+        Some(rom) => Some(rom_sha1(&fs::read(rom)?)),
+        None => None,
+    };
+
+    let file = BufWriter::new(File::create(output)?);
+    write_bk2(file, game_name, sha1.as_deref(), &frames)?;
+    println!("Exported {} frames to {}", frames.len(), output.display());
+    Ok(())
+}
+
+/// Writes a `.bk2` archive holding one frame of input per entry of `frames`, each entry holding
+/// the state of every port, player 1 first. Ports without a joypad are written as idle
+/// controllers, and multitaps are plugged in for more than 2 players.
+pub fn write_bk2<W: Write + Seek>(
+    writer: W,
+    game_name: &str,
+    sha1: Option<&str>,
+    frames: &[Vec<PortState>],
+) -> Result<W, Box<dyn Error>> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut archive = ZipWriter::new(writer);
+    let ports = frames.iter().map(Vec::len).max().unwrap_or(1);
+    let (left_port, right_port, players) = port_devices(ports);
+
+    archive.start_file("Header.txt", options)?;
+    archive.write_all(header(game_name, sha1).as_bytes())?;
+
+    archive.start_file("SyncSettings.json", options)?;
+    let sync_settings = serde_json::json!({ "o": {
+        "$type": SYNC_SETTINGS_TYPE,
+        "LeftPort": left_port,
+        "RightPort": right_port,
+    } });
+    archive.write_all(sync_settings.to_string().as_bytes())?;
+
+    archive.start_file("Input Log.txt", options)?;
+    archive.write_all(input_log(frames, players).as_bytes())?;
+
+    Ok(archive.finish()?)
+}
+
+fn header(game_name: &str, sha1: Option<&str>) -> String {
+    let mut lines = vec![
+        "MovieVersion BizHawk v2.0.0".to_string(),
+        "Platform SNES".to_string(),
+        "Core BSNES".to_string(),
+        format!("GameName {game_name}"),
+    ];
+    lines.extend(sha1.map(|sha1| format!("SHA1 {sha1}")));
+    lines.extend(["rerecordCount 0".to_string(), String::new()]);
+    lines.join("\r\n")
+}
+
+/// The input log of `players` joypads, the ones missing from `frames` being idle.
+fn input_log(frames: &[Vec<PortState>], players: usize) -> String {
+    let mut log = String::from("[Input]\r\nLogKey:#Reset|Power|");
+    for player in 1..=players {
+        log.push('#');
+        for (_, name, _) in COLUMNS {
+            log.push_str(&format!("P{player} {name}|"));
+        }
+    }
+    log.push_str("\r\n");
+
+    for frame in frames {
+        log.push_str("|..|");
+        for player in 0..players {
+            let held = frame
+                .get(player)
+                .and_then(PortState::joypad)
+                .unwrap_or_default();
+            for (button, _, mnemonic) in COLUMNS {
+                log.push(if held.contains(button) { mnemonic } else { '.' });
+            }
+            log.push('|');
+        }
+        log.push_str("\r\n");
+    }
+    log.push_str("[/Input]\r\n");
+    log
+}
+
+/// Reads the inputs of `player` from a `.bk2` archive. Columns are located through the
+/// `LogKey` line, so movies from any SNES core and with any number of controllers work.
+pub fn read_movie<R: Read + Seek>(reader: R, player: usize) -> Result<Movie, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(reader)?;
+
+    let mut header = String::new();
+    archive.by_name("Header.txt")?.read_to_string(&mut header)?;
+    let frame_rate = match header.lines().any(|l| l.trim() == "PAL True") {
+        true => PAL_FRAME_RATE,
+        false => NTSC_FRAME_RATE,
+    };
+
+    let mut log = String::new();
+    archive.by_name("Input Log.txt")?.read_to_string(&mut log)?;
+    let log_key = log
+        .lines()
+        .find_map(|l| l.strip_prefix("LogKey:"))
+        .ok_or("Input Log.txt has no LogKey")?;
+
+    // Group and column index of every button of the player.
+    let prefix = format!("P{} ", player + 1);
+    let mut columns: Vec<(usize, usize, Pressed)> = Vec::new();
+    for (group, keys) in log_key.split('#').skip(1).enumerate() {
+        for (column, key) in keys.split('|').enumerate() {
+            let button = key
+                .strip_prefix(&prefix)
+                .and_then(|name| COLUMNS.iter().find(|(_, n, _)| *n == name));
+            if let Some((pressed, _, _)) = button {
+                columns.push((group, column, *pressed));
+            }
+        }
+    }
+    if columns.is_empty() {
+        return Err(format!("the movie has no inputs for player {}", player + 1).into());
+    }
+
+    let frames = log
+        .lines()
+        .filter(|l| l.starts_with('|'))
+        .map(|line| {
+            let groups: Vec<Vec<char>> = line
+                .trim_matches('|')
+                .split('|')
+                .map(split_columns)
+                .collect();
+            let held = columns.iter().filter_map(|&(group, column, pressed)| {
+                let value = groups.get(group)?.get(column)?;
+                (*value != '.').then_some(pressed)
+            });
+            held.collect()
+        })
+        .collect();
+
+    Ok(Movie { frames, frame_rate })
+}
+
+/// Splits an input log group into one character per column. Analog columns, written as
+/// comma terminated numbers, are collapsed to a single placeholder character.
+fn split_columns(group: &str) -> Vec<char> {
+    let mut columns = Vec::new();
+    let mut chars = group.chars();
+    while let Some(c) = chars.next() {
+        if c == ' ' || c == '-' || c.is_ascii_digit() {
+            chars.by_ref().find(|&c| c == ',');
+            columns.push('#');
+        } else {
+            columns.push(c);
+        }
+    }
+    columns
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::time::Duration;
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::controller::{button_state::ButtonState, frame_counter::FrameCount};
+    use crate::recording::input_log::Sample;
+
+    #[test]
+    fn exports_frames_with_snes_column_order() {
+        let sample = |millis: u64, bytes: [u8; 2]| Sample {
+            sequence: 0,
+            elapsed: Duration::from_millis(millis),
+            frame: None,
+            ports: vec![PortState::Joypad(ButtonState::from_le_bytes(bytes))],
+        };
+        // B+Right from the start, A+L+Up on the second frame, released on the third.
+        let samples = [
+            sample(0, [0x00, 0x81]),
+            sample(16, [0xA0, 0x08]),
+            sample(33, [0x00, 0x00]),
+        ];
+        let frames = samples_to_frames(&samples, NTSC_FRAME_RATE);
+        assert_eq!(frames.len(), 3);
+
+        let bytes = write_bk2(Cursor::new(Vec::new()), "Test", None, &frames)
+            .unwrap()
+            .into_inner();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut log = String::new();
+        archive
+            .by_name("Input Log.txt")
+            .unwrap()
+            .read_to_string(&mut log)
+            .unwrap();
+
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            lines[1],
+            "LogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Select|P1 Start|P1 Y|P1 B|P1 X|P1 A|P1 L|P1 R|\
+             #P2 Up|P2 Down|P2 Left|P2 Right|P2 Select|P2 Start|P2 Y|P2 B|P2 X|P2 A|P2 L|P2 R|"
+        );
+        assert_eq!(lines[2], "|..|...R...B....|............|");
+        assert_eq!(lines[3], "|..|U........Al.|............|");
+        assert_eq!(lines[4], "|..|............|............|");
+        assert_eq!(lines[5], "[/Input]");
+        assert!(archive.by_name("Header.txt").is_ok());
+    }
+
+    #[test]
+    fn plugs_multitaps_for_more_than_two_players() {
+        let frames = vec![vec![PortState::Joypad(ButtonState::default()); 3]];
+        let bytes = write_bk2(
+            Cursor::new(Vec::new()),
+            "SUPER BOMBERMAN",
+            Some("ABC123"),
+            &frames,
+        )
+        .unwrap()
+        .into_inner();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            let mut file = archive.by_name(name).unwrap();
+            file.read_to_string(&mut content).unwrap();
+            content
+        };
+
+        let header = read("Header.txt");
+        assert!(header.contains("GameName SUPER BOMBERMAN\r\nSHA1 ABC123\r\n"));
+        let settings: serde_json::Value = serde_json::from_str(&read("SyncSettings.json")).unwrap();
+        assert_eq!(settings["o"]["LeftPort"], GAMEPAD);
+        assert_eq!(settings["o"]["RightPort"], SUPER_MULTITAP);
+        let log = read("Input Log.txt");
+        assert_eq!(log.lines().nth(2).unwrap().matches('|').count(), 7);
+        assert_eq!(port_devices(8), (SUPER_MULTITAP, SUPER_MULTITAP, 8));
+
+        // Copier headers are not part of the hash
+        let rom = vec![0xFF; 0x8000];
+        let copier_dump = [vec![0; 512], rom.clone()].concat();
+        assert_eq!(rom_sha1(&rom), rom_sha1(&copier_dump));
+        assert_eq!(rom_sha1(b""), "DA39A3EE5E6B4B0D3255BFEF95601890AFD80709");
+    }
+
+    #[test]
+    fn places_frame_tagged_samples_on_their_frame() {
+        let sample = |millis: u64, frame: u32, low: u8| Sample {
+            sequence: 0,
+            elapsed: Duration::from_millis(millis),
+            frame: Some(FrameCount {
+                value: frame,
+                bytes: 1,
+            }),
+            ports: vec![PortState::Joypad(ButtonState::from_le_bytes([low, 0]))],
+        };
+        // Frame 0xFF read twice, 0x01 skipped, timestamps delayed by the USB jitter
+        let samples = [
+            sample(40, 0xFE, 0x80),
+            sample(45, 0xFF, 0x00),
+            sample(70, 0xFF, 0x40),
+            sample(120, 0x02, 0x80),
+        ];
+        let frames = samples_to_frames(&samples, NTSC_FRAME_RATE);
+        let low_bytes: Vec<u8> = frames
+            .iter()
+            .map(|ports| ports[0].joypad().unwrap().to_le_bytes()[0])
+            .collect();
+        assert_eq!(low_bytes, [0x80, 0x40, 0x40, 0x40, 0x80]);
+    }
+
+    #[test]
+    fn imports_players_through_the_log_key() {
+        let log = "[Input]\r\n\
+            LogKey:#Reset|Power|#P1 Mouse X|P1 Mouse Y|P1 Left|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Select|P2 Start|P2 Y|P2 B|P2 X|P2 A|P2 L|P2 R|\r\n\
+            |..|  -12,    3,L|U...s.......|\r\n\
+            |..|    0,    0,.|.......B...r|\r\n\
+            [/Input]\r\n";
+        let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file("Header.txt", SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(b"Platform SNES\r\n").unwrap();
+        archive
+            .start_file("Input Log.txt", SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(log.as_bytes()).unwrap();
+        let bytes = archive.finish().unwrap();
+
+        let movie = read_movie(Cursor::new(bytes.into_inner()), 1).unwrap();
+        assert_eq!(movie.frame_rate, NTSC_FRAME_RATE);
+        let frames: Vec<Vec<Pressed>> = movie.frames.iter().map(|f| f.iter().collect()).collect();
+        assert_eq!(frames[0], vec![Pressed::Select, Pressed::Up]);
+        assert_eq!(frames[1], vec![Pressed::B, Pressed::R]);
+
+        // Exports define two gamepads, without a third player
+        let bytes = write_bk2(Cursor::new(Vec::new()), "", None, &[]).unwrap();
+        assert!(read_movie(Cursor::new(bytes.into_inner()), 2).is_err());
+    }
+}
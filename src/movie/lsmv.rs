@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::io::{Read, Seek};
+
+use zip::ZipArchive;
+
+use crate::controller::pressed::Pressed;
+use crate::movie::{Movie, NTSC_FRAME_RATE, PAL_FRAME_RATE};
+
+/// Buttons of an lsnes gamepad, in the order of their column in the input file.
+const GAMEPAD_BUTTONS: [Pressed; 12] = [
+    Pressed::B,
+    Pressed::Y,
+    Pressed::Select,
+    Pressed::Start,
+    Pressed::Up,
+    Pressed::Down,
+    Pressed::Left,
+    Pressed::Right,
+    Pressed::A,
+    Pressed::X,
+    Pressed::L,
+    Pressed::R,
+];
+
+/// Reads the inputs of `player` from an lsnes `.lsmv` archive. Controllers are numbered in the
+/// order of their field in the input file, so players 2 to 5 of a multitap on port 2 follow
+/// player 1.
+pub fn read_movie<R: Read + Seek>(reader: R, player: usize) -> Result<Movie, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(reader)?;
+
+    let mut gametype = String::new();
+    if let Ok(mut file) = archive.by_name("gametype") {
+        file.read_to_string(&mut gametype)?;
+    }
+    let frame_rate = match gametype.contains("pal") {
+        true => PAL_FRAME_RATE,
+        false => NTSC_FRAME_RATE,
+    };
+
+    let mut input = String::new();
+    archive.by_name("input")?.read_to_string(&mut input)?;
+
+    let has_player = input
+        .lines()
+        .any(|l| l.starts_with('F') && l.split('|').nth(player + 1).is_some());
+    if !has_player {
+        return Err(format!("the movie has no inputs for player {}", player + 1).into());
+    }
+
+    // Lines starting with `F` begin a new frame, the others are subframes and are skipped.
+    let frames = input
+        .lines()
+        .filter(|l| l.starts_with('F'))
+        .map(|line| {
+            let controller = line.split('|').nth(player + 1).unwrap_or_default();
+            let held = controller
+                .chars()
+                .zip(GAMEPAD_BUTTONS)
+                .filter(|(c, _)| *c != '.' && *c != ' ')
+                .map(|(_, button)| button);
+            held.collect()
+        })
+        .collect();
+
+    Ok(Movie { frames, frame_rate })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    #[test]
+    fn reads_frames_and_skips_subframes() {
+        let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file("gametype", SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(b"snes_pal").unwrap();
+        archive
+            .start_file("input", SimpleFileOptions::default())
+            .unwrap();
+        archive
+            .write_all(b"F.|B...........|....u.......\n.|BY..........|............\nF.|............|........AX..\n")
+            .unwrap();
+        let bytes = archive.finish().unwrap().into_inner();
+
+        let movie = read_movie(Cursor::new(bytes.clone()), 1).unwrap();
+        assert_eq!(movie.frame_rate, PAL_FRAME_RATE);
+        let frames: Vec<Vec<Pressed>> = movie.frames.iter().map(|f| f.iter().collect()).collect();
+        assert_eq!(
+            frames,
+            vec![vec![Pressed::Up], vec![Pressed::A, Pressed::X]]
+        );
+
+        assert!(read_movie(Cursor::new(bytes), 2).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::io::Read;
+
+use crate::controller::button_state::ButtonState;
+use crate::movie::{Movie, NTSC_FRAME_RATE, PAL_FRAME_RATE};
+
+const SIGNATURE: &[u8; 4] = b"SMV\x1A";
+const FRAME_COUNT_OFFSET: usize = 0x10;
+const CONTROLLER_MASK_OFFSET: usize = 0x14;
+const MOVIE_OPTIONS_OFFSET: usize = 0x15;
+const CONTROLLER_DATA_OFFSET: usize = 0x1C;
+/// Bit of the movie options flagging a PAL movie.
+const OPTION_PAL: u8 = 0x02;
+
+/// Reads the inputs of `player` from a Snes9x `.smv` movie. Each frame stores two bytes per
+/// controller enabled in the header mask, in the SNES joypad register layout.
+pub fn read_movie(mut reader: impl Read, player: usize) -> Result<Movie, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < 0x20 || &bytes[..4] != SIGNATURE {
+        return Err("not a Snes9x movie file".into());
+    }
+    let u32_at =
+        |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+
+    let controller_mask = bytes[CONTROLLER_MASK_OFFSET];
+    if player >= 5 || controller_mask & (1 << player) == 0 {
+        return Err(format!("the movie has no inputs for player {}", player + 1).into());
+    }
+    let controllers = controller_mask.count_ones() as usize;
+    let player_offset = (controller_mask & ((1 << player) - 1)).count_ones() as usize * 2;
+    let frame_size = controllers * 2;
+
+    // The movie holds one more sample than its frame count, for the power-on frame.
+    let frame_count = u32_at(FRAME_COUNT_OFFSET) + 1;
+    let data = bytes
+        .get(u32_at(CONTROLLER_DATA_OFFSET)..)
+        .ok_or("controller data offset is out of the file")?;
+    let frames = data
+        .chunks_exact(frame_size)
+        .take(frame_count)
+        .map(|f| ButtonState::from_le_bytes([f[player_offset], f[player_offset + 1]]))
+        .collect();
+
+    let frame_rate = match bytes[MOVIE_OPTIONS_OFFSET] & OPTION_PAL {
+        0 => NTSC_FRAME_RATE,
+        _ => PAL_FRAME_RATE,
+    };
+    Ok(Movie { frames, frame_rate })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::pressed::Pressed;
+
+    #[test]
+    fn reads_selected_controller() {
+        let mut movie = SIGNATURE.to_vec();
+        movie.resize(0x20, 0);
+        movie[FRAME_COUNT_OFFSET] = 1;
+        // Controllers 1 and 3 plugged in.
+        movie[CONTROLLER_MASK_OFFSET] = 0b101;
+        movie[CONTROLLER_DATA_OFFSET] = 0x20;
+        movie.extend([0x00, 0x80, 0x80, 0x00]);
+        movie.extend([0x00, 0x00, 0x10, 0x08]);
+
+        let player_3 = read_movie(movie.as_slice(), 2).unwrap();
+        assert_eq!(player_3.frame_rate, NTSC_FRAME_RATE);
+        let frames: Vec<Vec<Pressed>> =
+            player_3.frames.iter().map(|f| f.iter().collect()).collect();
+        assert_eq!(
+            frames,
+            vec![vec![Pressed::A], vec![Pressed::Up, Pressed::R]]
+        );
+
+        assert!(read_movie(movie.as_slice(), 1).is_err());
+    }
+}
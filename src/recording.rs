@@ -0,0 +1,242 @@
+pub mod input_log;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::controller::{frame_counter::FrameCount, port::PortState};
+use crate::recording::input_log::{read_input_log, InputLogWriter, Record, Sample};
+
+/// File extension used for recorded input logs.
+pub const INPUT_LOG_EXTENSION: &str = "sidlog";
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct RecordingConfig {
+    /// Folder where recordings started with the hotkey are written.
+    pub directory: Option<PathBuf>,
+}
+
+impl RecordingConfig {
+    /// Returns a new timestamped file path in the configured recordings folder, defaulting to
+    /// `Documents/snes-input-display/recordings`.
+    pub fn new_session_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let directory = match self.directory {
+            Some(ref d) => d.clone(),
+            None => dirs::document_dir()
+                .ok_or("Could not compute Documents directory")?
+                .join("snes-input-display")
+                .join("recordings"),
+        };
+        fs::create_dir_all(&directory)?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(directory.join(format!("session-{timestamp}.{INPUT_LOG_EXTENSION}")))
+    }
+}
+
+/// A recorded session loaded back from an input log file.
+pub struct Session {
+    /// Every layout used during the session, in order of first use.
+    pub layouts: Vec<String>,
+    /// Title of the first ROM the session was recorded on, when it was known.
+    pub rom_title: Option<String>,
+    pub samples: Vec<Sample>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let records = read_input_log(BufReader::new(File::open(path)?))?;
+        let mut layouts: Vec<String> = Vec::new();
+        let mut rom_title = None;
+        let mut samples: Vec<Sample> = Vec::new();
+        for record in records {
+            match record {
+                Record::Sample(s) => samples.push(s),
+                Record::Rom(title) => {
+                    rom_title.get_or_insert(title);
+                }
+                Record::Layout(l) if !layouts.contains(&l) => layouts.push(l),
+                Record::Layout(_) => {}
+            }
+        }
+        if samples.is_empty() {
+            return Err(format!("{} contains no inputs", path.display()).into());
+        }
+        Ok(Self {
+            layouts,
+            rom_title,
+            samples,
+        })
+    }
+}
+
+/// Writes every polled `PortState` of a session to an input log file.
+pub struct Recorder {
+    writer: InputLogWriter<BufWriter<File>>,
+    path: PathBuf,
+    started: Instant,
+    layout: String,
+    rom_title: Option<String>,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, layout: &str) -> io::Result<Self> {
+        let mut writer = InputLogWriter::new(BufWriter::new(File::create(path)?))?;
+        writer.write_layout(layout)?;
+        println!("Recording inputs to {}", path.display());
+
+        Ok(Self {
+            writer,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+            layout: layout.to_string(),
+            rom_title: None,
+        })
+    }
+
+    /// Writes a ROM record if `title` is not the title of the ROM recorded last.
+    pub fn set_rom(&mut self, title: &str) -> io::Result<()> {
+        if self.rom_title.as_deref() == Some(title) {
+            return Ok(());
+        }
+        self.writer.write_rom(title)?;
+        self.rom_title = Some(title.to_string());
+        Ok(())
+    }
+
+    /// Appends the states of every port polled at `polled_at` during `frame`, preceded by a
+    /// layout record if the layout changed since the previous sample.
+    pub fn record(
+        &mut self,
+        sequence: u32,
+        polled_at: Instant,
+        layout: &str,
+        frame: Option<FrameCount>,
+        ports: &[PortState],
+    ) -> io::Result<()> {
+        if layout != self.layout {
+            self.writer.write_layout(layout)?;
+            self.layout = layout.to_string();
+        }
+        self.writer.write_sample(&Sample {
+            sequence,
+            elapsed: polled_at.saturating_duration_since(self.started),
+            frame,
+            ports: ports.to_vec(),
+        })
+    }
+
+    /// Flushes the file and returns its path.
+    pub fn stop(mut self) -> io::Result<PathBuf> {
+        self.writer.flush()?;
+        println!("Recording saved to {}", self.path.display());
+        Ok(self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::controller::{
+        button_state::ButtonState, frame_counter::FrameCount, mouse::MouseState, port::PortState,
+    };
+    use crate::recording::input_log::{
+        read_input_log, InputLogWriter, Record, Sample, MAGIC, VERSION,
+    };
+
+    #[test]
+    fn input_log_encoding() {
+        let mut writer = InputLogWriter::new(Vec::new()).unwrap();
+        writer.write_layout("SMW").unwrap();
+        writer.write_rom("MARIO").unwrap();
+        writer
+            .write_sample(&Sample {
+                sequence: 7,
+                elapsed: Duration::from_micros(0x0102),
+                frame: None,
+                ports: vec![
+                    PortState::Joypad(ButtonState::from_le_bytes([0x80, 0x01])),
+                    PortState::Mouse(MouseState {
+                        left: true,
+                        delta_x: -2,
+                        ..MouseState::default()
+                    }),
+                ],
+            })
+            .unwrap();
+
+        let mut expected = MAGIC.to_vec();
+        expected.push(VERSION);
+        expected.extend([0x01, 3, 0, b'S', b'M', b'W']);
+        expected.extend([0x04, 5, 0, b'M', b'A', b'R', b'I', b'O']);
+        expected.extend([0x02, 7, 0, 0, 0, 0x02, 0x01, 0, 0, 0, 0, 0, 0]);
+        expected.extend([2, 0x00, 0x80, 0x01, 0x01, 0x00, 0x41, 0x00, 0x82]);
+        let bytes = writer.into_inner();
+        assert_eq!(bytes, expected);
+
+        match &read_input_log(bytes.as_slice()).unwrap()[..] {
+            [Record::Layout(_), Record::Rom(title), Record::Sample(s)] => {
+                assert_eq!(title, "MARIO");
+                assert!(matches!(s.ports[1], PortState::Mouse(m) if m.left && m.delta_x == -2))
+            }
+            records => panic!("unexpected records {records:?}"),
+        }
+    }
+
+    #[test]
+    fn reads_version_1_single_port_samples() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend([0x02, 7, 0, 0, 0, 0x02, 0x01, 0, 0, 0, 0, 0, 0, 0x80, 0x01]);
+
+        match &read_input_log(bytes.as_slice()).unwrap()[..] {
+            [Record::Sample(s)] => {
+                assert_eq!(s.sequence, 7);
+                assert_eq!(s.ports.len(), 1);
+                assert_eq!(s.ports[0].joypad().unwrap().to_le_bytes(), [0x80, 0x01]);
+            }
+            records => panic!("unexpected records {records:?}"),
+        }
+    }
+
+    #[test]
+    fn input_log_round_trip() {
+        let mut writer = InputLogWriter::new(Vec::new()).unwrap();
+        writer.write_layout("Default").unwrap();
+        for sequence in 1..=3 {
+            writer
+                .write_sample(&Sample {
+                    sequence,
+                    elapsed: Duration::from_millis(16 * sequence as u64),
+                    frame: Some(FrameCount {
+                        value: 0x12340 + sequence,
+                        bytes: 4,
+                    }),
+                    ports: vec![PortState::Joypad(ButtonState::from_le_bytes([0, 0x80]))],
+                })
+                .unwrap();
+        }
+        let mut bytes = writer.into_inner();
+        // Simulate a session that was not stopped cleanly.
+        bytes.truncate(bytes.len() - 4);
+
+        let records = read_input_log(bytes.as_slice()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(matches!(&records[0], Record::Layout(l) if l == "Default"));
+        match &records[2] {
+            Record::Sample(s) => {
+                assert_eq!(s.sequence, 2);
+                assert_eq!(s.elapsed, Duration::from_millis(32));
+                assert_eq!(s.frame.map(|f| f.value), Some(0x12342));
+                assert_eq!(s.ports[0].joypad().unwrap().to_le_bytes(), [0, 0x80]);
+            }
+            _ => panic!("expected a sample record"),
+        }
+
+        assert!(read_input_log(&b"NOTLOG\x02"[..]).is_err());
+    }
+}
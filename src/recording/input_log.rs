@@ -0,0 +1,249 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use crate::controller::{
+    button_state::ButtonState, frame_counter::FrameCount, light_gun::LightGunState,
+    mouse::MouseState, port::PortState,
+};
+
+/// Magic bytes at the start of every input log file.
+pub const MAGIC: &[u8; 6] = b"SIDLOG";
+/// Current version of the input log format, stored right after `MAGIC`. Version 1 samples hold
+/// a single controller port, version 2 samples only hold joypad states, version 3 logs have no
+/// frame records, version 4 logs have no ROM records.
+pub const VERSION: u8 = 5;
+
+/// Marks a layout record: a `u16` length followed by the UTF-8 layout name. Every sample that
+/// follows was read with this layout.
+pub const TAG_LAYOUT: u8 = 0x01;
+/// Marks a sample record: `u32` poll sequence number, `u64` microseconds since the start of the
+/// recording, a `u8` port count and the state of each port, all little-endian. Every port
+/// state starts with a device byte.
+pub const TAG_SAMPLE: u8 = 0x02;
+/// Marks the in-game frame of the sample record that follows: `u8` size of the frame counter
+/// in bytes and `u32` counter value.
+pub const TAG_FRAME: u8 = 0x03;
+/// Marks a ROM record: a `u16` length followed by the UTF-8 title from the header of the ROM
+/// running when the samples that follow were read.
+pub const TAG_ROM: u8 = 0x04;
+
+/// Port holding a joypad, followed by its `u16` button state.
+pub const DEVICE_JOYPAD: u8 = 0x00;
+/// Port holding a mouse, followed by its 4 bytes serial report.
+pub const DEVICE_MOUSE: u8 = 0x01;
+/// Port holding a light gun, followed by its buttons byte and `u16` H and V latches.
+pub const DEVICE_LIGHT_GUN: u8 = 0x02;
+
+/// The controller states of a single poll.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub sequence: u32,
+    pub elapsed: Duration,
+    /// In-game frame of the poll, when the layout has a frame counter.
+    pub frame: Option<FrameCount>,
+    /// One state per controller port, player 1 first.
+    pub ports: Vec<PortState>,
+}
+
+/// A record read back from an input log.
+#[derive(Debug, Clone)]
+pub enum Record {
+    Layout(String),
+    Rom(String),
+    Sample(Sample),
+}
+
+/// Writes the input log format to any `Write` implementation.
+pub struct InputLogWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> InputLogWriter<W> {
+    /// Writes the file header and returns a writer ready to accept records.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        Ok(Self { inner })
+    }
+
+    pub fn write_layout(&mut self, layout: &str) -> io::Result<()> {
+        self.write_string(TAG_LAYOUT, layout)
+    }
+
+    pub fn write_rom(&mut self, title: &str) -> io::Result<()> {
+        self.write_string(TAG_ROM, title)
+    }
+
+    /// Writes a `tag` record holding a `u16` length and the UTF-8 `value`.
+    fn write_string(&mut self, tag: u8, value: &str) -> io::Result<()> {
+        let length = u16::try_from(value.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too long"))?;
+        self.inner.write_all(&[tag])?;
+        self.inner.write_all(&length.to_le_bytes())?;
+        self.inner.write_all(value.as_bytes())
+    }
+
+    pub fn write_sample(&mut self, sample: &Sample) -> io::Result<()> {
+        let micros = u64::try_from(sample.elapsed.as_micros()).unwrap_or(u64::MAX);
+        let port_count = u8::try_from(sample.ports.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many ports"))?;
+        if let Some(frame) = sample.frame {
+            self.inner.write_all(&[TAG_FRAME, frame.bytes])?;
+            self.inner.write_all(&frame.value.to_le_bytes())?;
+        }
+        self.inner.write_all(&[TAG_SAMPLE])?;
+        self.inner.write_all(&sample.sequence.to_le_bytes())?;
+        self.inner.write_all(&micros.to_le_bytes())?;
+        self.inner.write_all(&[port_count])?;
+        for state in &sample.ports {
+            match state {
+                PortState::Joypad(buttons) => {
+                    self.inner.write_all(&[DEVICE_JOYPAD])?;
+                    self.inner.write_all(&buttons.to_le_bytes())?;
+                }
+                PortState::Mouse(mouse) => {
+                    self.inner.write_all(&[DEVICE_MOUSE])?;
+                    self.inner.write_all(&mouse.to_report())?;
+                }
+                PortState::LightGun(gun) => {
+                    self.inner.write_all(&[DEVICE_LIGHT_GUN])?;
+                    self.inner.write_all(&gun.to_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads every record of an input log. A truncated trailing record, as left by a session that
+/// was not stopped cleanly, is ignored.
+pub fn read_input_log(mut reader: impl Read) -> io::Result<Vec<Record>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let mut cursor = bytes.as_slice();
+
+    let header = take(&mut cursor, MAGIC.len() + 1)?;
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(invalid_data("not an input log file"));
+    }
+    let version = header[MAGIC.len()];
+    if version == 0 || version > VERSION {
+        return Err(invalid_data(&format!(
+            "unsupported input log version {version}"
+        )));
+    }
+
+    let mut records = Vec::new();
+    let mut frame = None;
+    while let Some((&tag, rest)) = cursor.split_first() {
+        cursor = rest;
+        let record = match tag {
+            TAG_LAYOUT => read_string(&mut cursor).map(Record::Layout),
+            TAG_ROM => read_string(&mut cursor).map(Record::Rom),
+            TAG_SAMPLE => read_sample(&mut cursor, version, frame.take()),
+            TAG_FRAME => match read_frame(&mut cursor) {
+                Ok(f) => {
+                    frame = Some(f);
+                    continue;
+                }
+                Err(e) => Err(e),
+            },
+            _ => return Err(invalid_data(&format!("unknown record tag {tag:#04x}"))),
+        };
+        match record {
+            Ok(r) => records.push(r),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(records)
+}
+
+/// Reads the `u16` length and UTF-8 value of a layout or ROM record.
+fn read_string(cursor: &mut &[u8]) -> io::Result<String> {
+    let length = u16::from_le_bytes(array(take(cursor, 2)?)) as usize;
+    let value = std::str::from_utf8(take(cursor, length)?)
+        .map_err(|_| invalid_data("record is not valid UTF-8"))?;
+    Ok(value.to_string())
+}
+
+fn read_frame(cursor: &mut &[u8]) -> io::Result<FrameCount> {
+    let bytes = take(cursor, 1)?[0];
+    let value = u32::from_le_bytes(array(take(cursor, 4)?));
+    Ok(FrameCount { value, bytes })
+}
+
+fn read_sample(cursor: &mut &[u8], version: u8, frame: Option<FrameCount>) -> io::Result<Record> {
+    let sequence = u32::from_le_bytes(array(take(cursor, 4)?));
+    let micros = u64::from_le_bytes(array(take(cursor, 8)?));
+    let port_count = match version {
+        1 => 1,
+        _ => take(cursor, 1)?[0] as usize,
+    };
+    let ports = match version {
+        1 | 2 => take(cursor, port_count * 2)?
+            .chunks_exact(2)
+            .map(|bytes| PortState::Joypad(ButtonState::from_le_bytes(array(bytes))))
+            .collect(),
+        _ => (0..port_count)
+            .map(|_| read_port(cursor))
+            .collect::<io::Result<_>>()?,
+    };
+    Ok(Record::Sample(Sample {
+        sequence,
+        elapsed: Duration::from_micros(micros),
+        frame,
+        ports,
+    }))
+}
+
+fn read_port(cursor: &mut &[u8]) -> io::Result<PortState> {
+    match take(cursor, 1)?[0] {
+        DEVICE_JOYPAD => {
+            let bytes = array(take(cursor, 2)?);
+            Ok(PortState::Joypad(ButtonState::from_le_bytes(bytes)))
+        }
+        DEVICE_MOUSE => {
+            let report = array(take(cursor, 4)?);
+            Ok(PortState::Mouse(MouseState::from_report(report)))
+        }
+        DEVICE_LIGHT_GUN => {
+            let [buttons, x_low, x_high, y_low, y_high] = array(take(cursor, 5)?);
+            let gun = LightGunState::from_bytes(buttons, [x_low, x_high], [y_low, y_high]);
+            Ok(PortState::LightGun(gun))
+        }
+        device => Err(invalid_data(&format!("unknown device {device:#04x}"))),
+    }
+}
+
+fn take<'a>(cursor: &mut &'a [u8], length: usize) -> io::Result<&'a [u8]> {
+    if cursor.len() < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated input log",
+        ));
+    }
+    let (head, tail) = cursor.split_at(length);
+    *cursor = tail;
+    Ok(head)
+}
+
+/// Converts a slice returned by `take` into a fixed size array.
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    bytes
+        .try_into()
+        .expect("take returned the requested length")
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
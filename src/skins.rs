@@ -1,10 +1,14 @@
 mod button;
 mod button_map;
+pub mod light_gun_skin;
+pub mod mouse_skin;
 pub mod skin;
+mod stick;
 mod theme;
 
 use crate::skins::button::Button;
 use crate::skins::button_map::ButtonsMap;
+use crate::skins::stick::Stick;
 use crate::skins::theme::Theme;
 
 use ggez::Context;
@@ -24,16 +28,43 @@ use std::{
 
 use crate::controller::pressed::Pressed;
 
-type LayoutResult = Result<(Vec<Theme>, BTreeMap<Pressed, Button>), Box<dyn Error>>;
+type LayoutResult = Result<SkinLayout, Box<dyn Error>>;
 type AttributeResult = Result<HashMap<String, String>, Box<dyn Error>>;
 
+/// Elements of a `skin.xml` file, as written in the file.
+struct SkinLayout {
+    backgrounds: Vec<Theme>,
+    buttons: Vec<Button>,
+    sticks: Vec<Stick>,
+}
+
+/// Loads the skin `name` from the skins directory at `path`, returning the background of
+/// `theme` and every element of the skin.
+fn load_skin(
+    path: &Path,
+    name: &str,
+    theme: &String,
+    ctx: &mut Context,
+) -> Result<(Theme, SkinLayout), Box<dyn Error>> {
+    let skin_filename = "skin.xml";
+    let file_path = path.join(name).join(skin_filename);
+
+    let mut layout = get_layout(file_path, name, ctx)?;
+    let background = match parse_backgrounds(std::mem::take(&mut layout.backgrounds), theme) {
+        Some(t) => t,
+        None => return Err("could not parse background".into()),
+    };
+    Ok((background, layout))
+}
+
 fn get_layout(file_path: PathBuf, name: &str, ctx: &mut Context) -> LayoutResult {
     let file = load_file(&file_path)?;
     // let layout_name = Path::new(name);
     let mut reader = Reader::from_str(&file);
     let mut _metadata: HashMap<String, String> = HashMap::new();
     let mut backgrounds: Vec<Theme> = Vec::new();
-    let mut buttons: BTreeMap<Pressed, Button> = BTreeMap::new();
+    let mut buttons: Vec<Button> = Vec::new();
+    let mut sticks: Vec<Stick> = Vec::new();
 
     loop {
         match reader.read_event() {
@@ -44,7 +75,11 @@ fn get_layout(file_path: PathBuf, name: &str, ctx: &mut Context) -> LayoutResult
                 }
                 b"button" => {
                     let bt = Button::new(t, name, ctx)?;
-                    buttons.insert(bt.name, bt);
+                    buttons.push(bt);
+                }
+                b"stick" => {
+                    let st = Stick::new(t, name, ctx)?;
+                    sticks.push(st);
                 }
                 _ => {}
             },
@@ -53,7 +88,11 @@ fn get_layout(file_path: PathBuf, name: &str, ctx: &mut Context) -> LayoutResult
             _ => (),
         }
     }
-    Ok((backgrounds, buttons))
+    Ok(SkinLayout {
+        backgrounds,
+        buttons,
+        sticks,
+    })
 }
 
 fn load_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
@@ -99,10 +138,10 @@ fn buttons_map_to_array_generic<T>(
         if let Some(item) = map.remove(key) {
             vec.push(item);
         } else {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Missing button: {:?}", key),
-            )));
+            return Err(Box::new(io::Error::other(format!(
+                "Missing button: {:?}",
+                key
+            ))));
         }
     }
 
@@ -126,7 +165,7 @@ fn buttons_map_to_array(
 fn parse_attributes(t: BytesStart) -> AttributeResult {
     let mut attributes_map = HashMap::new();
     for attr in t.attributes().with_checks(false) {
-        let attr = attr.map_err(|e| Box::<dyn Error>::from(e))?;
+        let attr = attr.map_err(Box::<dyn Error>::from)?;
         let key_bytes = attr.key.local_name().into_inner();
         let key = std::str::from_utf8(key_bytes)?.to_string();
         let value = attr.unescape_value()?.into_owned();
@@ -1,4 +1,3 @@
-use crate::controller::pressed::Pressed;
 use crate::skins::parse_attributes;
 
 use ggez::{
@@ -9,9 +8,10 @@ use ggez::{
 use quick_xml::events::BytesStart;
 use std::{error::Error, path::Path, path::MAIN_SEPARATOR_STR};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Button {
-    pub name: Pressed,
+    /// Name of the input in the skin, e.g. `a` or `up` on a joypad.
+    pub name: String,
     pub image: Image,
     pub rect: Rect,
 }
@@ -29,24 +29,8 @@ impl Button {
         let width = image.width() as f32;
         let height = image.height() as f32;
 
-        let name = match attributes["name"].as_str() {
-            "a" => Pressed::A,
-            "b" => Pressed::B,
-            "x" => Pressed::X,
-            "y" => Pressed::Y,
-            "select" => Pressed::Select,
-            "start" => Pressed::Start,
-            "l" => Pressed::L,
-            "r" => Pressed::R,
-            "up" => Pressed::Up,
-            "down" => Pressed::Down,
-            "left" => Pressed::Left,
-            "right" => Pressed::Right,
-            _ => panic!(),
-        };
-
         Ok(Self {
-            name,
+            name: attributes.get("name").ok_or("missing name")?.to_owned(),
             image,
             rect: Rect::new(x, y, width, height),
         })
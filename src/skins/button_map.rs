@@ -4,7 +4,7 @@ use crate::skins::Button;
 
 /// A wrapper over an array `[Button; 12]` indexable by `Pressed`. The array is internally ordered
 /// by a button's bit ascending from lowest bit to highest.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ButtonsMap(pub [Button; 12]);
 
 impl std::ops::Index<Pressed> for ButtonsMap {
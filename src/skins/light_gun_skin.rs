@@ -0,0 +1,100 @@
+use crate::controller::light_gun::LightGunState;
+use crate::skins::button::Button;
+use crate::skins::{load_skin, Theme};
+use ggez::{
+    graphics::{Canvas, Color, DrawParam, Quad, Rect},
+    Context,
+};
+
+use std::{error::Error, path::Path};
+
+/// Half length of the crosshair lines, in pixels.
+const CROSSHAIR_SIZE: f32 = 8.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+/// Skin of a light gun: the background stands for the screen, a crosshair is drawn at the
+/// aimed position. `trigger`, `cursor`, `turbo` and `pause` buttons are drawn when the skin
+/// has them.
+#[derive(Clone)]
+pub struct LightGunSkin {
+    pub background: Theme,
+    pub trigger: Option<Button>,
+    pub cursor: Option<Button>,
+    pub turbo: Option<Button>,
+    pub pause: Option<Button>,
+}
+
+impl LightGunSkin {
+    pub fn new(
+        path: &Path,
+        name: &str,
+        theme: &String,
+        ctx: &mut Context,
+    ) -> Result<LightGunSkin, Box<dyn Error>> {
+        let (background, layout) = load_skin(path, name, theme, ctx)?;
+        let mut buttons = layout.buttons;
+        let mut take_button = |name: &str| {
+            let index = buttons.iter().position(|b| b.name == name)?;
+            Some(buttons.swap_remove(index))
+        };
+        Ok(Self {
+            background,
+            trigger: take_button("trigger"),
+            cursor: take_button("cursor"),
+            turbo: take_button("turbo"),
+            pause: take_button("pause"),
+        })
+    }
+
+    /// Draws the background with its top left corner at `origin`, then the buttons held and
+    /// the crosshair.
+    pub fn draw(&self, canvas: &mut Canvas, origin: [f32; 2], state: Option<&LightGunState>) {
+        let [x, y] = origin;
+        canvas.draw(&self.background.image, DrawParam::new().dest(origin));
+        let Some(state) = state else {
+            return;
+        };
+
+        let held = [
+            (state.trigger, &self.trigger),
+            (state.cursor, &self.cursor),
+            (state.turbo, &self.turbo),
+            (state.pause, &self.pause),
+        ];
+        for (_, button) in held.iter().filter(|(held, _)| *held) {
+            if let Some(button) = button {
+                let point = button.rect.point();
+                canvas.draw(
+                    &button.image,
+                    DrawParam::default().dest([point.x + x, point.y + y]),
+                );
+            }
+        }
+
+        if let Some((aim_x, aim_y)) = state.normalized_position() {
+            let center_x = x + aim_x * self.background.image.width() as f32;
+            let center_y = y + aim_y * self.background.height;
+            let color = match state.trigger {
+                true => Color::RED,
+                false => Color::WHITE,
+            };
+            let lines = [
+                Rect::new(
+                    center_x - CROSSHAIR_SIZE,
+                    center_y - CROSSHAIR_THICKNESS / 2.0,
+                    CROSSHAIR_SIZE * 2.0,
+                    CROSSHAIR_THICKNESS,
+                ),
+                Rect::new(
+                    center_x - CROSSHAIR_THICKNESS / 2.0,
+                    center_y - CROSSHAIR_SIZE,
+                    CROSSHAIR_THICKNESS,
+                    CROSSHAIR_SIZE * 2.0,
+                ),
+            ];
+            for line in lines {
+                canvas.draw(&Quad, DrawParam::default().dest_rect(line).color(color));
+            }
+        }
+    }
+}
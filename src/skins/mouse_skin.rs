@@ -0,0 +1,76 @@
+use crate::controller::mouse::MouseState;
+use crate::skins::button::Button;
+use crate::skins::stick::Stick;
+use crate::skins::{load_skin, Theme};
+use ggez::{
+    graphics::{Canvas, DrawParam},
+    Context,
+};
+
+use std::{error::Error, path::Path};
+
+/// Mouse motion drawn at the edge of the motion indicator.
+const MOTION_FULL_SCALE: f32 = 16.0;
+
+/// Skin of a SNES Mouse: `left` and `right` buttons, and an optional stick showing the motion
+/// of the last poll.
+#[derive(Clone)]
+pub struct MouseSkin {
+    pub background: Theme,
+    pub left: Button,
+    pub right: Button,
+    pub motion: Option<Stick>,
+}
+
+impl MouseSkin {
+    pub fn new(
+        path: &Path,
+        name: &str,
+        theme: &String,
+        ctx: &mut Context,
+    ) -> Result<MouseSkin, Box<dyn Error>> {
+        let (background, layout) = load_skin(path, name, theme, ctx)?;
+        let mut buttons = layout.buttons;
+        let mut take_button = |name: &str| -> Result<Button, Box<dyn Error>> {
+            let index = buttons
+                .iter()
+                .position(|b| b.name == name)
+                .ok_or(format!("Missing button: {name}"))?;
+            Ok(buttons.swap_remove(index))
+        };
+        Ok(Self {
+            background,
+            left: take_button("left")?,
+            right: take_button("right")?,
+            motion: layout.sticks.into_iter().next(),
+        })
+    }
+
+    /// Draws the background with its top left corner at `origin`, then the buttons held and
+    /// the motion of the mouse.
+    pub fn draw(&self, canvas: &mut Canvas, origin: [f32; 2], state: Option<&MouseState>) {
+        let [x, y] = origin;
+        canvas.draw(&self.background.image, DrawParam::new().dest(origin));
+        let Some(state) = state else {
+            return;
+        };
+
+        let held = [(state.left, &self.left), (state.right, &self.right)];
+        for (_, button) in held.iter().filter(|(held, _)| *held) {
+            let point = button.rect.point();
+            canvas.draw(
+                &button.image,
+                DrawParam::default().dest([point.x + x, point.y + y]),
+            );
+        }
+
+        if let Some(ref motion) = self.motion {
+            let (dx, dy) = state.normalized_motion(MOTION_FULL_SCALE);
+            let [stick_x, stick_y] = motion.position(dx, dy);
+            canvas.draw(
+                &motion.image,
+                DrawParam::default().dest([stick_x + x, stick_y + y]),
+            );
+        }
+    }
+}
@@ -1,8 +1,15 @@
+use crate::controller::{button_state::ButtonState, port::PortState, pressed::Pressed};
+use crate::skins::light_gun_skin::LightGunSkin;
+use crate::skins::mouse_skin::MouseSkin;
 use crate::skins::ButtonsMap;
 use crate::skins::Theme;
-use crate::skins::{buttons_map_to_array, get_layout, parse_backgrounds};
-use ggez::Context;
+use crate::skins::{buttons_map_to_array, load_skin};
+use ggez::{
+    graphics::{Canvas, DrawParam},
+    Context,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use std::{error::Error, path::Path};
@@ -12,9 +19,32 @@ pub struct SkinConfig {
     pub skins_path: PathBuf,
     pub skin_name: String,
     pub skin_theme: String,
+    /// How the skins of a multi-port layout are placed in the window.
+    #[serde(default)]
+    pub arrangement: Arrangement,
+    /// Skin of the ports holding a SNES Mouse, from the same skins folder.
+    pub mouse: Option<DeviceSkinConfig>,
+    /// Skin of the ports holding a Super Scope or Justifier.
+    pub light_gun: Option<DeviceSkinConfig>,
 }
 
-// #[derive(Debug)]
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DeviceSkinConfig {
+    pub skin_name: String,
+    pub skin_theme: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Arrangement {
+    /// Side by side, player 1 on the left.
+    #[default]
+    Horizontal,
+    /// Stacked, player 1 at the top.
+    Vertical,
+}
+
+#[derive(Clone)]
 pub struct Skin {
     // pub metadata: HashMap<String, String>,
     pub background: Theme,
@@ -27,21 +57,73 @@ pub struct Skin {
 impl Skin {
     pub fn new(
         path: &Path,
-        name: &String,
+        name: &str,
         theme: &String,
         ctx: &mut Context,
     ) -> Result<Skin, Box<dyn Error>> {
-        let skin_filename = "skin.xml";
-        let file_path = path.join(name).join(skin_filename);
-
-        let (backgrounds, buttons) = get_layout(file_path, name, ctx)?;
-        let background = match parse_backgrounds(backgrounds, theme) {
-            Some(t) => t,
-            None => return Err("could not parse background".into()),
-        };
+        let (background, layout) = load_skin(path, name, theme, ctx)?;
+        let mut buttons = BTreeMap::new();
+        for button in layout.buttons {
+            let pressed = Pressed::from_name(&button.name)
+                .ok_or(format!("unknown button {}", button.name))?;
+            buttons.insert(pressed, button);
+        }
         Ok(Self {
             background,
             buttons: buttons_map_to_array(buttons)?,
         })
     }
+
+    /// Draws the background with its top left corner at `origin`, then the buttons held.
+    pub fn draw(&self, canvas: &mut Canvas, origin: [f32; 2], state: Option<ButtonState>) {
+        let [x, y] = origin;
+        canvas.draw(&self.background.image, DrawParam::new().dest(origin));
+        state.iter().flat_map(ButtonState::iter).for_each(|event| {
+            let button = &self.buttons[event];
+            let point = button.rect.point();
+            canvas.draw(
+                &button.image,
+                DrawParam::default().dest([point.x + x, point.y + y]),
+            );
+        });
+    }
+}
+
+/// The skin of a single controller port, matching the device plugged in it.
+#[derive(Clone)]
+pub enum PortSkin {
+    Joypad(Skin),
+    Mouse(Box<MouseSkin>),
+    LightGun(Box<LightGunSkin>),
+}
+
+impl PortSkin {
+    pub fn background(&self) -> &Theme {
+        match self {
+            PortSkin::Joypad(skin) => &skin.background,
+            PortSkin::Mouse(skin) => &skin.background,
+            PortSkin::LightGun(skin) => &skin.background,
+        }
+    }
+
+    /// Draws the port at `origin`, with the inputs of `state` when it matches the device.
+    pub fn draw(&self, canvas: &mut Canvas, origin: [f32; 2], state: Option<&PortState>) {
+        match self {
+            PortSkin::Joypad(skin) => skin.draw(canvas, origin, state.and_then(PortState::joypad)),
+            PortSkin::Mouse(skin) => {
+                let mouse = match state {
+                    Some(PortState::Mouse(mouse)) => Some(mouse),
+                    _ => None,
+                };
+                skin.draw(canvas, origin, mouse)
+            }
+            PortSkin::LightGun(skin) => {
+                let gun = match state {
+                    Some(PortState::LightGun(gun)) => Some(gun),
+                    _ => None,
+                };
+                skin.draw(canvas, origin, gun)
+            }
+        }
+    }
 }
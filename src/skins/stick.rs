@@ -0,0 +1,44 @@
+use crate::skins::parse_attributes;
+
+use ggez::{graphics::Image, Context};
+
+use quick_xml::events::BytesStart;
+use std::{error::Error, path::Path, path::MAIN_SEPARATOR_STR};
+
+/// An image moved around its resting position by a pair of analog inputs, such as the motion
+/// of a mouse.
+#[derive(Debug, Clone)]
+pub struct Stick {
+    pub image: Image,
+    pub x: f32,
+    pub y: f32,
+    /// Largest horizontal offset from the resting position, in pixels.
+    pub x_range: f32,
+    /// Largest vertical offset from the resting position, in pixels.
+    pub y_range: f32,
+}
+
+impl Stick {
+    pub fn new(t: BytesStart, skin_dir: &str, ctx: &mut Context) -> Result<Self, Box<dyn Error>> {
+        let attributes = parse_attributes(t)?;
+        let number = |key: &str| -> Result<f32, Box<dyn Error>> {
+            let value = attributes.get(key).ok_or(format!("missing {key}"))?;
+            Ok(value.parse::<f32>()?)
+        };
+        let image_rel = attributes.get("image").ok_or("missing image")?;
+        let image_path = Path::new(MAIN_SEPARATOR_STR).join(skin_dir).join(image_rel);
+
+        Ok(Self {
+            image: Image::from_path(ctx, image_path)?,
+            x: number("x")?,
+            y: number("y")?,
+            x_range: number("xrange")?,
+            y_range: number("yrange")?,
+        })
+    }
+
+    /// Position of the image for analog values in -1.0..=1.0.
+    pub fn position(&self, x: f32, y: f32) -> [f32; 2] {
+        [self.x + x * self.x_range, self.y + y * self.y_range]
+    }
+}
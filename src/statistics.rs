@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::controller::{button_state::ButtonState, pressed::Pressed};
+
+/// Press and hold statistics of a single button.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ButtonStatistics {
+    pub presses: u32,
+    pub releases: u32,
+    /// Time held over every completed press.
+    pub total_held: Duration,
+    pub longest_hold: Duration,
+    held_since: Option<Instant>,
+}
+
+impl ButtonStatistics {
+    /// Average duration of a completed press.
+    pub fn average_hold(&self) -> Duration {
+        match self.releases {
+            0 => Duration::ZERO,
+            n => self.total_held / n,
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.held_since.is_some()
+    }
+}
+
+/// Statistics of a session, fed with every polled `ButtonState`.
+#[derive(Debug)]
+pub struct SessionStatistics {
+    buttons: BTreeMap<Pressed, ButtonStatistics>,
+    previous: ButtonState,
+}
+
+impl Default for SessionStatistics {
+    fn default() -> Self {
+        Self {
+            buttons: Pressed::ALL
+                .into_iter()
+                .map(|p| (p, ButtonStatistics::default()))
+                .collect(),
+            previous: ButtonState::default(),
+        }
+    }
+}
+
+impl SessionStatistics {
+    /// Accounts for `state` polled at `at`: buttons held now but not on the previous update
+    /// count as a press, buttons no longer held end their hold.
+    pub fn update(&mut self, at: Instant, state: ButtonState) {
+        let pressed = state.just_pressed(self.previous);
+        let released = state.just_released(self.previous);
+        self.previous = state;
+
+        for (button, stats) in self.buttons.iter_mut() {
+            if pressed.contains(*button) {
+                stats.presses += 1;
+                stats.held_since = Some(at);
+            } else if let (true, Some(since)) = (released.contains(*button), stats.held_since) {
+                let hold = at.saturating_duration_since(since);
+                stats.releases += 1;
+                stats.total_held += hold;
+                stats.longest_hold = stats.longest_hold.max(hold);
+                stats.held_since = None;
+            }
+        }
+    }
+
+    pub fn button(&self, pressed: Pressed) -> &ButtonStatistics {
+        &self.buttons[&pressed]
+    }
+
+    /// One line per button with its press count, average and longest hold. Buttons currently
+    /// held are marked with a `*`.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for pressed in Pressed::ALL {
+            let stats = self.button(pressed);
+            let _ = writeln!(
+                summary,
+                "{}{:<7}{:>6}x  avg {:>6.3}s  max {:>6.3}s",
+                if stats.is_held() { '*' } else { ' ' },
+                pressed.to_string(),
+                stats.presses,
+                stats.average_hold().as_secs_f32(),
+                stats.longest_hold.as_secs_f32(),
+            );
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_presses_and_hold_durations() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let b = ButtonState::from_le_bytes([0x00, 0x80]);
+        let b_and_a = ButtonState::from_le_bytes([0x80, 0x80]);
+        let none = ButtonState::default();
+
+        let mut statistics = SessionStatistics::default();
+        statistics.update(at(0), b);
+        statistics.update(at(100), b_and_a);
+        assert!(statistics.button(Pressed::A).is_held());
+        statistics.update(at(300), none);
+        statistics.update(at(400), b);
+        statistics.update(at(450), none);
+
+        let b_stats = statistics.button(Pressed::B);
+        assert_eq!(b_stats.presses, 2);
+        assert_eq!(b_stats.releases, 2);
+        assert_eq!(b_stats.total_held, Duration::from_millis(350));
+        assert_eq!(b_stats.average_hold(), Duration::from_millis(175));
+        assert_eq!(b_stats.longest_hold, Duration::from_millis(300));
+        assert!(!b_stats.is_held());
+
+        let a_stats = statistics.button(Pressed::A);
+        assert_eq!(a_stats.presses, 1);
+        assert_eq!(a_stats.longest_hold, Duration::from_millis(200));
+        assert_eq!(statistics.button(Pressed::X).presses, 0);
+    }
+}
@@ -0,0 +1,235 @@
+use ggez::graphics::{Canvas, Color, DrawParam, Quad, Rect, Text, TextFragment};
+use ggez::{Context, GameResult};
+use serde::{Deserialize, Serialize};
+
+use crate::input_source::{connection::ConnectionState, DeviceInfo};
+
+/// Room around the status text, and between the badge and the text.
+const PADDING: f32 = 4.0;
+
+/// Where the status badge and info panel are drawn.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// In a strip added under the skins and timeline, so it never covers the controller.
+    #[default]
+    Below,
+    /// Not drawn, connection changes are only logged.
+    Hidden,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct StatusConfig {
+    pub position: StatusPosition,
+    /// Show the device info panel at startup, it is toggled with the I key.
+    pub show_info: bool,
+    pub text_size: f32,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            position: StatusPosition::default(),
+            show_info: false,
+            text_size: 14.0,
+        }
+    }
+}
+
+/// Connection badge, last error and device info, drawn over a corner of the skins or in a
+/// strip under them.
+pub struct StatusLayer {
+    position: StatusPosition,
+    text_size: f32,
+    pub show_info: bool,
+    state: Option<ConnectionState>,
+    info: Option<DeviceInfo>,
+    /// Title from the header of the running ROM.
+    title: Option<String>,
+}
+
+impl StatusLayer {
+    pub fn new(config: &StatusConfig) -> Self {
+        Self {
+            position: config.position,
+            text_size: config.text_size,
+            show_info: config.show_info,
+            state: None,
+            info: None,
+            title: None,
+        }
+    }
+
+    pub fn connection_changed(&mut self, state: ConnectionState) {
+        if !matches!(
+            state,
+            ConnectionState::Attached { .. } | ConnectionState::Degraded { .. }
+        ) {
+            self.info = None;
+            self.title = None;
+        }
+        self.state = Some(state);
+    }
+
+    /// Device info and ROM title read when the source attached.
+    pub fn attached(&mut self, info: Option<DeviceInfo>, title: Option<String>) {
+        self.info = info;
+        self.title = title;
+    }
+
+    /// Height of the strip added under the skins, 0 unless the status is drawn below them.
+    pub fn height(&self) -> f32 {
+        match self.position {
+            StatusPosition::Below => self.text_size + 2.0 * PADDING,
+            _ => 0.0,
+        }
+    }
+
+    /// Green when attached, yellow while connecting or degraded, red when disconnected.
+    fn badge_color(&self) -> Color {
+        match self.state {
+            Some(ConnectionState::Attached { .. }) => Color::from_rgb(0x3C, 0xC8, 0x50),
+            Some(ConnectionState::Disconnected {
+                reason: Some(_), ..
+            }) => Color::from_rgb(0xE0, 0x30, 0x30),
+            _ => Color::from_rgb(0xF0, 0xC0, 0x20),
+        }
+    }
+
+    /// The connection state and `error`, then the device info when the panel is shown.
+    pub fn lines(&self, error: Option<&str>) -> Vec<String> {
+        let mut lines = vec![match self.state {
+            Some(ref state) => state.to_string(),
+            None => String::from("Starting"),
+        }];
+        lines.extend(error.map(String::from));
+        if !self.show_info {
+            return lines;
+        }
+        match self.info {
+            Some(ref info) => lines.extend([
+                format!("Type     {}", info.kind),
+                format!("Firmware {}", info.firmware),
+                format!("ROM      {}", info.rom),
+            ]),
+            None => lines.push(String::from("No device info")),
+        }
+        if let Some(ref title) = self.title {
+            lines.push(format!("Title    {title}"));
+        }
+        lines
+    }
+
+    /// Draws the status over the `width` x `height` area of the skins and timeline, or in the
+    /// strip under it.
+    pub fn draw(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        width: f32,
+        height: f32,
+        error: Option<&str>,
+    ) -> GameResult {
+        let separator = match self.position {
+            StatusPosition::Hidden => return Ok(()),
+            StatusPosition::Below => "  ",
+            _ => "\n",
+        };
+        let mut text = Text::new(TextFragment {
+            text: self.lines(error).join(separator),
+            color: Some(Color::WHITE),
+            ..Default::default()
+        });
+        text.set_scale(self.text_size);
+        let size = text.measure(ctx)?;
+        let panel_width = self.text_size + size.x + 3.0 * PADDING;
+        let panel_height = size.y + 2.0 * PADDING;
+        let (left, top) = match self.position {
+            StatusPosition::TopLeft => (0.0, 0.0),
+            StatusPosition::TopRight => (width - panel_width, 0.0),
+            StatusPosition::BottomLeft => (0.0, height - panel_height),
+            StatusPosition::BottomRight => (width - panel_width, height - panel_height),
+            _ => (0.0, height),
+        };
+
+        canvas.draw(
+            &Quad,
+            DrawParam::default()
+                .dest_rect(Rect::new(left, top, panel_width, panel_height))
+                .color(Color::from_rgba(0, 0, 0, 180)),
+        );
+        let badge = self.text_size * 0.7;
+        canvas.draw(
+            &Quad,
+            DrawParam::default()
+                .dest_rect(Rect::new(
+                    left + PADDING + (self.text_size - badge) / 2.0,
+                    top + PADDING + (self.text_size - badge) / 2.0,
+                    badge,
+                    badge,
+                ))
+                .color(self.badge_color()),
+        );
+        canvas.draw(
+            &text,
+            DrawParam::default().dest([left + self.text_size + 2.0 * PADDING, top + PADDING]),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_the_connection_and_device_info() {
+        let mut status = StatusLayer::new(&StatusConfig::default());
+        assert_eq!(status.lines(None), ["Starting"]);
+        assert_eq!(status.height(), 14.0 + 2.0 * PADDING);
+
+        status.connection_changed(ConnectionState::Attached {
+            device: String::from("SD2SNES COM3"),
+        });
+        status.attached(
+            Some(DeviceInfo {
+                firmware: String::from("1.11.0"),
+                kind: String::from("SD2SNES"),
+                rom: String::from("/games/smw.sfc"),
+            }),
+            Some(String::from("SUPER MARIOWORLD")),
+        );
+        assert_eq!(status.badge_color(), Color::from_rgb(0x3C, 0xC8, 0x50));
+        assert_eq!(
+            status.lines(Some("Recording stopped")),
+            ["Attached to SD2SNES COM3", "Recording stopped"]
+        );
+        status.show_info = true;
+        assert_eq!(
+            status.lines(None),
+            [
+                "Attached to SD2SNES COM3",
+                "Type     SD2SNES",
+                "Firmware 1.11.0",
+                "ROM      /games/smw.sfc",
+                "Title    SUPER MARIOWORLD"
+            ]
+        );
+
+        // The info is forgotten with the device
+        status.connection_changed(ConnectionState::Connecting { attempt: 1 });
+        assert_eq!(
+            status.lines(None),
+            ["Connecting (attempt 1)", "No device info"]
+        );
+
+        // Drawn over the skins in a corner
+        let config: StatusConfig = toml::from_str("position = \"top_left\"").unwrap();
+        assert_eq!(StatusLayer::new(&config).height(), 0.0);
+    }
+}
@@ -0,0 +1,254 @@
+use ggez::graphics::{Canvas, Color, DrawParam, Quad, Rect, Text, TextFragment};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use crate::controller::{button_state::ButtonState, pressed::Pressed};
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct TimelineConfig {
+    pub enabled: bool,
+    /// Seconds of history shown across the width of the window.
+    pub length_seconds: f32,
+    pub row_height: f32,
+    /// Colors as `#RRGGBB` or `#RRGGBBAA`.
+    pub background_color: String,
+    pub bar_color: String,
+    pub label_color: String,
+    /// Skin names of the buttons to show, one row each from top to bottom.
+    pub rows: Vec<String>,
+}
+
+impl Default for TimelineConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length_seconds: 3.0,
+            row_height: 12.0,
+            background_color: String::from("#101010"),
+            bar_color: String::from("#3CB4FF"),
+            label_color: String::from("#A0A0A0"),
+            rows: [
+                "up", "down", "left", "right", "b", "a", "y", "x", "l", "r", "select", "start",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Longest history shown, the samples of the whole window are kept in memory.
+const MAX_LENGTH_SECONDS: f32 = 600.0;
+
+/// Parses a `#RRGGBB` or `#RRGGBBAA` color.
+pub fn parse_color(hex: &str) -> Result<Color, Box<dyn Error>> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    let value = u32::from_str_radix(digits, 16)?;
+    match digits.len() {
+        6 => Ok(Color::from_rgb_u32(value)),
+        8 => Ok(Color::from_rgba_u32(value)),
+        _ => Err(format!("invalid color {hex}, expected #RRGGBB or #RRGGBBAA").into()),
+    }
+}
+
+/// A held interval of a timeline row, as durations before now.
+#[derive(Debug, PartialEq)]
+pub struct Bar {
+    pub row: usize,
+    pub start_ago: Duration,
+    pub end_ago: Duration,
+}
+
+/// Scrolling input history ("piano roll"), one row per button with a bar for every held
+/// interval. The most recent input is on the right.
+pub struct Timeline {
+    length: Duration,
+    row_height: f32,
+    background_color: Color,
+    bar_color: Color,
+    label_color: Color,
+    rows: Vec<Pressed>,
+    history: VecDeque<(Instant, ButtonState)>,
+}
+
+impl Timeline {
+    pub fn new(config: &TimelineConfig) -> Result<Self, Box<dyn Error>> {
+        let rows = config
+            .rows
+            .iter()
+            .map(|name| {
+                Pressed::from_name(&name.to_lowercase())
+                    .ok_or(format!("unknown timeline row {name}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !config.length_seconds.is_finite() {
+            return Err(format!("invalid timeline length {}", config.length_seconds).into());
+        }
+
+        Ok(Self {
+            length: Duration::from_secs_f32(config.length_seconds.clamp(0.1, MAX_LENGTH_SECONDS)),
+            row_height: config.row_height,
+            background_color: parse_color(&config.background_color)?,
+            bar_color: parse_color(&config.bar_color)?,
+            label_color: parse_color(&config.label_color)?,
+            rows,
+            history: VecDeque::new(),
+        })
+    }
+
+    /// Height of the timeline area, added under the skin background.
+    pub fn height(&self) -> f32 {
+        self.rows.len() as f32 * self.row_height
+    }
+
+    /// Adds a polled state and forgets the ones that scrolled out of the timeline.
+    pub fn push(&mut self, at: Instant, state: ButtonState) {
+        self.history.push_back((at, state));
+        // Keep the last state older than the window, it is still held at the left edge.
+        while self.history.len() > 1
+            && at.saturating_duration_since(self.history[1].0) > self.length
+        {
+            self.history.pop_front();
+        }
+    }
+
+    /// Held intervals visible at `now`.
+    pub fn bars(&self, now: Instant) -> Vec<Bar> {
+        let mut bars = Vec::new();
+        for (row, pressed) in self.rows.iter().enumerate() {
+            let mut held_since: Option<Instant> = None;
+            for (at, state) in &self.history {
+                let held = state.contains(*pressed);
+                match (held, held_since) {
+                    (true, None) => held_since = Some(*at),
+                    (false, Some(since)) => {
+                        bars.push(self.bar(row, now, since, *at));
+                        held_since = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(since) = held_since {
+                bars.push(self.bar(row, now, since, now));
+            }
+        }
+        bars.retain(|b| b.start_ago > b.end_ago);
+        bars
+    }
+
+    fn bar(&self, row: usize, now: Instant, start: Instant, end: Instant) -> Bar {
+        Bar {
+            row,
+            start_ago: now.saturating_duration_since(start).min(self.length),
+            end_ago: now.saturating_duration_since(end).min(self.length),
+        }
+    }
+
+    /// Draws the timeline in the `width` wide area starting at `top`.
+    pub fn draw(&self, canvas: &mut Canvas, top: f32, width: f32, now: Instant) {
+        canvas.draw(
+            &Quad,
+            DrawParam::default()
+                .dest_rect(Rect::new(0.0, top, width, self.height()))
+                .color(self.background_color),
+        );
+
+        let length = self.length.as_secs_f32();
+        let x = |ago: Duration| width - ago.as_secs_f32() / length * width;
+        for bar in self.bars(now) {
+            let left = x(bar.start_ago);
+            canvas.draw(
+                &Quad,
+                DrawParam::default()
+                    .dest_rect(Rect::new(
+                        left,
+                        top + bar.row as f32 * self.row_height + 1.0,
+                        x(bar.end_ago) - left,
+                        self.row_height - 2.0,
+                    ))
+                    .color(self.bar_color),
+            );
+        }
+
+        for (row, pressed) in self.rows.iter().enumerate() {
+            let mut label = Text::new(TextFragment {
+                text: pressed.to_string(),
+                color: Some(self.label_color),
+                ..Default::default()
+            });
+            label.set_scale(self.row_height);
+            canvas.draw(
+                &label,
+                DrawParam::default().dest([2.0, top + row as f32 * self.row_height]),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars_follow_held_intervals() {
+        let config = TimelineConfig {
+            length_seconds: 1.0,
+            rows: vec![String::from("B"), String::from("a")],
+            ..Default::default()
+        };
+        let mut timeline = Timeline::new(&config).unwrap();
+        assert_eq!(timeline.height(), 24.0);
+
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let ms = Duration::from_millis;
+        timeline.push(at(0), ButtonState::from_le_bytes([0x00, 0x80]));
+        timeline.push(at(500), ButtonState::from_le_bytes([0x80, 0x00]));
+        timeline.push(at(1200), ButtonState::from_le_bytes([0x80, 0x80]));
+
+        // B was pressed before the window, it is clipped at the left edge.
+        assert_eq!(
+            timeline.bars(at(1400)),
+            vec![
+                Bar {
+                    row: 0,
+                    start_ago: ms(1000),
+                    end_ago: ms(900)
+                },
+                Bar {
+                    row: 0,
+                    start_ago: ms(200),
+                    end_ago: ms(0)
+                },
+                Bar {
+                    row: 1,
+                    start_ago: ms(900),
+                    end_ago: ms(0)
+                },
+            ]
+        );
+
+        assert!(Timeline::new(&TimelineConfig {
+            rows: vec![String::from("turbo")],
+            ..Default::default()
+        })
+        .is_err());
+        assert!(parse_color("#12345").is_err());
+
+        for length_seconds in [f32::INFINITY, f32::NAN] {
+            let config = TimelineConfig {
+                length_seconds,
+                ..Default::default()
+            };
+            assert!(Timeline::new(&config).is_err());
+        }
+        let config = TimelineConfig {
+            length_seconds: 1e30,
+            ..Default::default()
+        };
+        let length = Duration::from_secs_f32(MAX_LENGTH_SECONDS);
+        assert_eq!(Timeline::new(&config).unwrap().length, length);
+    }
+}